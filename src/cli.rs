@@ -0,0 +1,496 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::vec::Vec;
+
+const RUN: &str = "run";
+const VAULT: &str = "vault";
+const ENCRYPT: &str = "encrypt";
+const DECRYPT: &str = "decrypt";
+
+const DEFAULT_PLAYBOOK: &str = "site.yml";
+const S3_SCHEME: &str = "s3://";
+
+const USAGE: &str = "usage: [run] [options] [playbook]
+       vault <encrypt|decrypt> <path> [--vault-password-file <path> | --vault-key-file <path>]
+
+The ferro binary runs the playbook given as a path (default site.yml).
+
+options:
+    --reporter <human|json|junit>  select the result reporter (default human)
+    --report-file <path>           write the report to a file instead of stdout
+    --no-color                     disable colored output
+    --parallelism <n>              run up to n independent tasks at once
+    --inventory <path>             load hosts from an inventory file
+    --limit <pattern>              restrict the run to hosts matching pattern
+    --state <path|s3://bucket/key> persist task outputs to a state file or S3 object
+    --state-lock-table <table>     lock S3 state with a DynamoDB table
+    --resume                       skip tasks completed by the previous failed run
+    --start-at-task <name>         skip every task defined before name
+    --step                         confirm each task before running it
+    --journal <path>               record run progress to path (default .ferro-journal.json)
+    --tags <tag,...>               only run tasks with one of the given tags
+    --skip-tags <tag,...>          skip tasks with any of the given tags
+    --list-tasks                   list the tasks that would run and exit
+    --list-tags                    list every tag in the playbook and exit
+    --vault-password-file <path>   read the vault password from path
+    --vault-key-file <path>        read a 32 byte vault key from path
+    --vault-vars <path>            load secret variables from an encrypted file
+    --help                         show this message";
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub playbook: Option<String>,
+    pub reporter: String,
+    pub report_file: Option<String>,
+    pub colored: bool,
+    pub parallelism: Option<usize>,
+    pub inventory: Vec<String>,
+    pub limit: Option<String>,
+    pub state: Option<String>,
+    pub state_lock_table: Option<String>,
+    pub resume: bool,
+    pub start_at_task: Option<String>,
+    pub step: bool,
+    pub journal: String,
+    pub tags: Vec<String>,
+    pub skip_tags: Vec<String>,
+    pub list_tasks: bool,
+    pub list_tags: bool,
+    pub vault_password_file: Option<String>,
+    pub vault_key_file: Option<String>,
+    pub vault_vars: Vec<String>,
+    pub vault_action: Option<(String, String)>,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            playbook: None,
+            reporter: crate::reporter::human::HUMAN.to_owned(),
+            report_file: None,
+            colored: true,
+            parallelism: None,
+            inventory: vec![],
+            limit: None,
+            state: None,
+            state_lock_table: None,
+            resume: false,
+            start_at_task: None,
+            step: false,
+            journal: crate::journal::DEFAULT_PATH.to_owned(),
+            tags: vec![],
+            skip_tags: vec![],
+            list_tasks: false,
+            list_tags: false,
+            vault_password_file: None,
+            vault_key_file: None,
+            vault_vars: vec![],
+            vault_action: None,
+            help: false,
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(args: Vec<String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter().peekable();
+        if args.peek().map_or(false, |a| a == RUN) {
+            args.next();
+        } else if args.peek().map_or(false, |a| a == VAULT) {
+            args.next();
+            let action = args.next().unwrap_or_default();
+            if action != ENCRYPT && action != DECRYPT {
+                return Err(format!("unknown vault action {}", action));
+            }
+            let path = args
+                .next()
+                .ok_or_else(|| format!("vault {} requires a path", action))?;
+            options.vault_action = Some((action, path));
+        }
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("option {} requires a value", name))
+            };
+            match arg.as_str() {
+                "--reporter" => options.reporter = value(&arg)?,
+                "--report-file" => options.report_file = Some(value(&arg)?),
+                "--no-color" => options.colored = false,
+                "--parallelism" => {
+                    let n = value(&arg)?;
+                    let n = n
+                        .parse()
+                        .map_err(|_| format!("invalid parallelism {}", n))?;
+                    options.parallelism = Some(n);
+                }
+                "--inventory" | "-i" => options.inventory.push(value(&arg)?),
+                "--limit" => options.limit = Some(value(&arg)?),
+                "--state" => options.state = Some(value(&arg)?),
+                "--state-lock-table" => options.state_lock_table = Some(value(&arg)?),
+                "--resume" => options.resume = true,
+                "--start-at-task" => options.start_at_task = Some(value(&arg)?),
+                "--step" => options.step = true,
+                "--journal" => options.journal = value(&arg)?,
+                "--tags" => options.tags.extend(split(&value(&arg)?)),
+                "--skip-tags" => options.skip_tags.extend(split(&value(&arg)?)),
+                "--list-tasks" => options.list_tasks = true,
+                "--list-tags" => options.list_tags = true,
+                "--vault-password-file" => options.vault_password_file = Some(value(&arg)?),
+                "--vault-key-file" => options.vault_key_file = Some(value(&arg)?),
+                "--vault-vars" => options.vault_vars.push(value(&arg)?),
+                "--help" | "-h" => options.help = true,
+                _ if !arg.starts_with('-')
+                    && options.playbook.is_none()
+                    && options.vault_action.is_none() =>
+                {
+                    options.playbook = Some(arg)
+                }
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+        Ok(options)
+    }
+
+    // Applies everything except the reporter, which creates the report file
+    // and so is left to reporter() once the playbook is actually run.
+    pub fn apply(&self, playbook: &mut crate::ferro::Playbook) -> Result<(), String> {
+        if let Some(parallelism) = self.parallelism {
+            playbook.parallelism = parallelism;
+        }
+        if !self.inventory.is_empty() {
+            let sources: Vec<Box<dyn crate::inventory::Source>> = self
+                .inventory
+                .iter()
+                .map(|path| {
+                    Box::new(crate::inventory::file::File { path: path.into() })
+                        as Box<dyn crate::inventory::Source>
+                })
+                .collect();
+            playbook.inventory =
+                Some(crate::inventory::Inventory::load(&sources).map_err(|e| e.to_string())?);
+        }
+        playbook.limit = self.limit.clone();
+        playbook.state_backend = self.state_backend()?;
+        if self.resume && self.start_at_task.is_some() {
+            return Err("--resume and --start-at-task are mutually exclusive".to_owned());
+        }
+        playbook.journal = Some(self.journal.clone().into());
+        if self.resume {
+            playbook.start = crate::ferro::Start::Resume;
+        }
+        if let Some(task) = &self.start_at_task {
+            playbook.start = crate::ferro::Start::AtTask(task.clone());
+        }
+        playbook.step = self.step;
+        playbook.tags = self.tags.clone();
+        playbook.skip_tags = self.skip_tags.clone();
+        playbook.vault = self.vault()?;
+        playbook
+            .vault_vars
+            .extend(self.vault_vars.iter().map(|v| v.into()));
+        Ok(())
+    }
+
+    fn state_backend(&self) -> Result<Option<Box<dyn crate::state::Backend>>, String> {
+        let state = match &self.state {
+            Some(state) => state,
+            None if self.state_lock_table.is_some() => {
+                return Err("--state-lock-table requires --state".to_owned())
+            }
+            None => return Ok(None),
+        };
+        match s3_location(state) {
+            Some((bucket, key)) => Ok(Some(Box::new(crate::state::s3::S3::new(
+                bucket,
+                key,
+                self.state_lock_table.clone(),
+                None,
+            )))),
+            None if state.starts_with(S3_SCHEME) => Err(format!(
+                "invalid S3 state {}, expected s3://bucket/key",
+                state
+            )),
+            None if self.state_lock_table.is_some() => {
+                Err("--state-lock-table requires S3 state".to_owned())
+            }
+            None => Ok(Some(Box::new(crate::state::file::File {
+                path: state.into(),
+            }))),
+        }
+    }
+
+    pub fn reporter(&self) -> Result<Box<dyn crate::reporter::Reporter>, String> {
+        let out =
+            crate::reporter::writer(self.report_file.as_deref()).map_err(|e| e.to_string())?;
+        crate::reporter::from_name(&self.reporter, out, self.colored)
+    }
+
+    fn vault(&self) -> Result<Option<crate::vault::Vault>, String> {
+        let vault = match (&self.vault_password_file, &self.vault_key_file) {
+            (Some(_), Some(_)) => {
+                return Err(
+                    "--vault-password-file and --vault-key-file are mutually exclusive".to_owned(),
+                )
+            }
+            (Some(path), None) => crate::vault::Vault::from_password_file(path.as_ref()),
+            (None, Some(path)) => crate::vault::Vault::from_key_file(path.as_ref()),
+            (None, None) => return Ok(None),
+        };
+        vault.map(Some).map_err(|e| e.to_string())
+    }
+
+    fn run_vault(&self, action: &str, path: &str) -> Result<(), String> {
+        let vault = self
+            .vault()?
+            .ok_or_else(|| "--vault-password-file or --vault-key-file is required".to_owned())?;
+        let contents = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        let contents = if action == ENCRYPT {
+            if crate::vault::is_encrypted(&String::from_utf8_lossy(&contents)) {
+                return Err(format!("{} is already encrypted", path));
+            }
+            vault.encrypt(&contents).map(|c| c.into_bytes())
+        } else {
+            vault.decrypt(&String::from_utf8_lossy(&contents))
+        }
+        .map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+// Splits s3://bucket/key into the bucket and key.
+fn s3_location(state: &str) -> Option<(String, String)> {
+    let location = state.strip_prefix(S3_SCHEME)?;
+    match location.find('/') {
+        Some(i) if i > 0 && i + 1 < location.len() => {
+            Some((location[..i].to_owned(), location[i + 1..].to_owned()))
+        }
+        _ => None,
+    }
+}
+
+fn split(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_owned())
+        .collect()
+}
+
+// Runs a playbook built in code, such as with the playbook! macro.
+pub fn main(playbook: crate::ferro::Playbook) {
+    execute(|options| match &options.playbook {
+        Some(path) => Err(format!("unknown argument {}", path)),
+        None => Ok(playbook),
+    });
+}
+
+// Runs the YAML playbook named on the command line.
+pub fn main_from_file() {
+    execute(|options| {
+        let path = options.playbook.as_deref().unwrap_or(DEFAULT_PLAYBOOK);
+        crate::include::playbook(
+            Path::new(path),
+            &[PathBuf::from(crate::role::DEFAULT_ROLES_PATH)],
+        )
+        .map_err(|e| format!("{}: {}", path, e))
+    });
+}
+
+// The playbook is loaded only once the options are known to need it, so
+// --help and vault actions work without one.
+fn execute<F>(load: F)
+where
+    F: FnOnce(&Options) -> Result<crate::ferro::Playbook, String>,
+{
+    let options = match Options::parse(env::args().skip(1).collect()) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    if options.help {
+        println!("{}", USAGE);
+        return;
+    }
+    if let Some((action, path)) = &options.vault_action {
+        if let Err(e) = options.run_vault(action, path) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    let mut playbook = match load(&options) {
+        Ok(playbook) => playbook,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = options.apply(&mut playbook) {
+        eprintln!("{}", e);
+        process::exit(2);
+    }
+
+    if options.list_tasks {
+        for (depth, task) in playbook.selected_tasks() {
+            let indent = "  ".repeat(depth);
+            if task.tags.is_empty() {
+                println!("{}{}", indent, task.description);
+            } else {
+                println!(
+                    "{}{}\tTAGS: [{}]",
+                    indent,
+                    task.description,
+                    task.tags.join(", ")
+                );
+            }
+        }
+        return;
+    }
+    if options.list_tags {
+        for tag in playbook.all_tags() {
+            println!("{}", tag);
+        }
+        return;
+    }
+
+    match options.reporter() {
+        Ok(reporter) => playbook.reporter = reporter,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    }
+    if !playbook.run().succeeded() {
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let options = Options::parse(args(&[
+            "run",
+            "--reporter",
+            "junit",
+            "--report-file",
+            "report.xml",
+            "--no-color",
+            "--parallelism",
+            "4",
+            "--start-at-task",
+            "deploy stack",
+            "--step",
+            "--tags",
+            "deploy, setup",
+            "--skip-tags",
+            "debug",
+        ]))
+        .unwrap();
+        assert_eq!(options.reporter, "junit");
+        assert_eq!(options.report_file, Some("report.xml".to_owned()));
+        assert!(!options.colored);
+        assert_eq!(options.parallelism, Some(4));
+        assert_eq!(options.start_at_task, Some("deploy stack".to_owned()));
+        assert!(options.step);
+        assert!(!options.resume);
+        assert_eq!(options.tags, vec!["deploy", "setup"]);
+        assert_eq!(options.skip_tags, vec!["debug"]);
+
+        let options = Options::parse(args(&[
+            "vault",
+            "encrypt",
+            "secrets.yml",
+            "--vault-password-file",
+            "pw",
+        ]))
+        .unwrap();
+        assert_eq!(
+            options.vault_action,
+            Some(("encrypt".to_owned(), "secrets.yml".to_owned()))
+        );
+        assert_eq!(options.vault_password_file, Some("pw".to_owned()));
+        assert!(Options::parse(args(&["vault", "rekey", "secrets.yml"])).is_err());
+
+        let options = Options::parse(args(&[
+            "deploy.yml",
+            "-i",
+            "hosts",
+            "--state",
+            "s3://bucket/ferro/state.json",
+            "--state-lock-table",
+            "locks",
+        ]))
+        .unwrap();
+        assert_eq!(options.playbook, Some("deploy.yml".to_owned()));
+        assert_eq!(options.inventory, vec!["hosts"]);
+        assert_eq!(options.state_lock_table, Some("locks".to_owned()));
+        assert!(Options::parse(args(&["deploy.yml", "other.yml"])).is_err());
+
+        assert!(Options::parse(args(&["--reporter"])).is_err());
+        assert!(Options::parse(args(&["--bogus"])).is_err());
+        assert_eq!(Options::parse(vec![]).unwrap(), Options::default());
+    }
+
+    #[test]
+    fn test_apply_leaves_report_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = dir.path().join("report.xml");
+        let options = Options::parse(args(&[
+            "--report-file",
+            &report.to_string_lossy(),
+            "--list-tasks",
+            "--tags",
+            "deploy",
+        ]))
+        .unwrap();
+        let mut playbook = crate::ferro::Playbook::default();
+        options.apply(&mut playbook).unwrap();
+        assert_eq!(playbook.tags, vec!["deploy"]);
+        assert!(!report.exists());
+
+        options.reporter().unwrap();
+        assert!(report.exists());
+    }
+
+    #[test]
+    fn test_state_backend() {
+        assert_eq!(
+            s3_location("s3://bucket/ferro/state.json"),
+            Some(("bucket".to_owned(), "ferro/state.json".to_owned()))
+        );
+        assert_eq!(s3_location("s3://bucket"), None);
+        assert_eq!(s3_location("s3:///key"), None);
+        assert_eq!(s3_location("state.json"), None);
+
+        let options = |state: &[&str]| Options::parse(args(state)).unwrap();
+        assert!(options(&[]).state_backend().unwrap().is_none());
+        assert!(options(&["--state", "state.json"])
+            .state_backend()
+            .unwrap()
+            .is_some());
+        assert!(options(&["--state", "s3://bucket"])
+            .state_backend()
+            .is_err());
+        assert!(
+            options(&["--state", "state.json", "--state-lock-table", "locks"])
+                .state_backend()
+                .is_err()
+        );
+        assert!(options(&["--state-lock-table", "locks"])
+            .state_backend()
+            .is_err());
+    }
+}
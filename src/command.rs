@@ -1,13 +1,287 @@
 use std::io;
 use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
 use std::vec::Vec;
 
-pub fn run(command: String, args: Vec<String>) -> Result<process::Output, io::Error> {
-    process::Command::new(command)
+pub const MAX_OUTPUT_BYTES: usize = 1024 * 1024;
+const MAX_LINE_BYTES: usize = 64 * 1024;
+const CHUNK_BYTES: usize = 8 * 1024;
+
+#[derive(Clone, Debug, Default)]
+pub struct Stream {
+    pub prefix: String,
+    pub quiet: bool,
+    pub redact: Vec<String>,
+}
+
+impl Stream {
+    pub fn new(prefix: &str) -> Self {
+        Stream {
+            prefix: prefix.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn line(&self, text: &str) -> String {
+        let line = format!("[{}] {}", self.prefix, text.trim_end_matches('\n'));
+        self.redact
+            .iter()
+            .filter(|r| !r.is_empty())
+            .fold(line, |line, r| {
+                line.replace(r.as_str(), crate::vault::REDACTED)
+            })
+    }
+}
+
+#[derive(Debug)]
+pub struct Captured {
+    pub status: process::ExitStatus,
+    pub duration: Duration,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+}
+
+pub fn run(command: String, args: Vec<String>, output: &Stream) -> Result<Captured, io::Error> {
+    let start = Instant::now();
+    let mut child = process::Command::new(command)
         .args(args)
         .stdin(process::Stdio::null())
         .stdout(process::Stdio::piped())
         .stderr(process::Stdio::piped())
-        .spawn()
-        .and_then(|child| child.wait_with_output())
+        .spawn()?;
+
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let stdout_output = output.clone();
+    let stderr_output = output.clone();
+    let stdout_reader = thread::spawn(move || stream(stdout, &stdout_output, io::stderr()));
+    let stderr_reader = thread::spawn(move || stream(stderr, &stderr_output, io::stderr()));
+
+    let status = child.wait()?;
+    let (stdout, stdout_truncated) = join(stdout_reader)?;
+    let (stderr, stderr_truncated) = join(stderr_reader)?;
+
+    Ok(Captured {
+        status: status,
+        duration: start.elapsed(),
+        stdout: stdout,
+        stderr: stderr,
+        stdout_truncated: stdout_truncated,
+        stderr_truncated: stderr_truncated,
+    })
+}
+
+fn stream(
+    mut source: impl io::Read,
+    output: &Stream,
+    mut sink: impl io::Write,
+) -> Result<(Vec<u8>, bool), io::Error> {
+    let mut retained = vec![];
+    let mut truncated = false;
+    let mut chunk = [0; CHUNK_BYTES];
+    let mut line = vec![];
+    loop {
+        let read = match source.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let bytes = &chunk[..read];
+
+        if !truncated {
+            let room = MAX_OUTPUT_BYTES - retained.len();
+            if bytes.len() > room {
+                // The first byte left out shows whether a character would be
+                // cut in two, which may have started in an earlier chunk.
+                retained.extend_from_slice(&bytes[..=room]);
+                retained.truncate(char_boundary(&retained, MAX_OUTPUT_BYTES));
+                truncated = true;
+            } else {
+                retained.extend_from_slice(bytes);
+            }
+        }
+
+        // Lines longer than MAX_LINE_BYTES are streamed in pieces so a
+        // command printing without newlines cannot grow the buffer.
+        for piece in bytes.split_inclusive(|b| *b == b'\n') {
+            line.extend_from_slice(piece);
+            if piece.ends_with(b"\n") {
+                write_line(&mut sink, output, &line);
+                line.clear();
+            } else if line.len() >= MAX_LINE_BYTES {
+                let split = split_point(&line, &output.redact);
+                write_line(&mut sink, output, &line[..split]);
+                line.drain(..split);
+            }
+        }
+    }
+    if !line.is_empty() {
+        write_line(&mut sink, output, &line);
+    }
+    Ok((retained, truncated))
+}
+
+// Backs off from index to the start of the character it falls in.
+fn char_boundary(bytes: &[u8], mut index: usize) -> usize {
+    while index > 0 && index < bytes.len() && bytes[index] & 0xc0 == 0x80 {
+        index -= 1;
+    }
+    index
+}
+
+// Where to split a long line so that neither a character nor a secret is cut
+// in two; the bytes after it are kept for the next piece. A secret that could
+// still be completed by later output is kept whole as well.
+fn split_point(line: &[u8], redact: &[String]) -> usize {
+    let longest = redact.iter().map(|r| r.len()).max().unwrap_or(0);
+    let mut split = char_boundary(line, line.len().saturating_sub(longest.saturating_sub(1)));
+    loop {
+        let straddling = redact
+            .iter()
+            .filter(|r| !r.is_empty())
+            .flat_map(|r| {
+                line.windows(r.len())
+                    .enumerate()
+                    .filter(move |(_, w)| *w == r.as_bytes())
+                    .map(move |(start, _)| start + r.len())
+                    .filter(move |end| end - r.len() < split && *end > split)
+            })
+            .max();
+        match straddling {
+            Some(end) => split = end,
+            None => break,
+        }
+    }
+    if split == 0 {
+        line.len()
+    } else {
+        split
+    }
+}
+
+fn write_line(sink: &mut impl io::Write, output: &Stream, line: &[u8]) {
+    if !output.quiet {
+        let _ = writeln!(sink, "{}", output.line(&String::from_utf8_lossy(line)));
+    }
+}
+
+fn join(
+    handle: thread::JoinHandle<Result<(Vec<u8>, bool), io::Error>>,
+) -> Result<(Vec<u8>, bool), io::Error> {
+    handle.join().unwrap_or_else(|_| {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "output reader panicked",
+        ))
+    })
+}
+
+pub fn decode(bytes: Vec<u8>) -> (String, Option<String>) {
+    match String::from_utf8(bytes) {
+        Ok(s) => (s, None),
+        Err(e) => {
+            let bytes = e.into_bytes();
+            (
+                String::from_utf8_lossy(&bytes).into_owned(),
+                Some(base64::encode(&bytes)),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let captured = run(
+            "/bin/sh".to_owned(),
+            vec!["-c".to_owned(), "echo out; echo err >&2; exit 3".to_owned()],
+            &Stream::new("test"),
+        )
+        .unwrap();
+        assert_eq!(captured.status.code(), Some(3));
+        assert_eq!(captured.stdout, b"out\n");
+        assert_eq!(captured.stderr, b"err\n");
+        assert!(!captured.stdout_truncated);
+    }
+
+    #[test]
+    fn test_stream_truncates() {
+        let input = vec![b'x'; MAX_OUTPUT_BYTES + 10];
+        let (retained, truncated) = stream(&input[..], &Stream::new("test"), io::sink()).unwrap();
+        assert_eq!(retained.len(), MAX_OUTPUT_BYTES);
+        assert!(truncated);
+    }
+
+    #[test]
+    fn test_stream_splits_long_lines() {
+        let mut input = vec![b'x'; MAX_LINE_BYTES * 2 + 10];
+        input.extend_from_slice(b"\ndone\n");
+        let mut sink = vec![];
+        let (retained, truncated) = stream(&input[..], &Stream::new("t"), &mut sink).unwrap();
+        assert_eq!(retained, input);
+        assert!(!truncated);
+        let lines: Vec<usize> = String::from_utf8(sink)
+            .unwrap()
+            .lines()
+            .map(|l| l.len())
+            .collect();
+        assert!(lines.iter().all(|l| *l <= MAX_LINE_BYTES + CHUNK_BYTES + 4));
+        assert_eq!(lines.last(), Some(&"[t] done".len()));
+    }
+
+    #[test]
+    fn test_stream_redacts() {
+        let output = Stream {
+            prefix: "login".to_owned(),
+            quiet: false,
+            redact: vec!["s3cret".to_owned()],
+        };
+        assert_eq!(
+            output.line("password s3cret\n"),
+            "[login] password ********"
+        );
+    }
+
+    #[test]
+    fn test_stream_splits_at_boundaries() {
+        let output = Stream {
+            prefix: "t".to_owned(),
+            quiet: false,
+            redact: vec!["s3cret".to_owned()],
+        };
+        let mut input = vec![b'x'; MAX_LINE_BYTES - 3];
+        input.extend_from_slice(b"s3cret and more\n");
+        input.extend_from_slice(&vec![b'y'; MAX_LINE_BYTES - 1]);
+        input.extend_from_slice("é\n".as_bytes());
+        let mut sink = vec![];
+        stream(&input[..], &output, &mut sink).unwrap();
+        let printed = String::from_utf8(sink).unwrap();
+        assert!(!printed.contains("s3c") && !printed.contains("cret"));
+        assert!(printed.contains(crate::vault::REDACTED));
+        assert!(printed.contains('é'));
+        assert!(!printed.contains('\u{fffd}'));
+
+        let mut input = vec![b'x'; MAX_OUTPUT_BYTES - 1];
+        input.extend_from_slice("éé".as_bytes());
+        let (retained, truncated) = stream(&input[..], &Stream::new("t"), io::sink()).unwrap();
+        assert!(truncated);
+        assert_eq!(retained.len(), MAX_OUTPUT_BYTES - 1);
+        assert!(String::from_utf8(retained).is_ok());
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"hello".to_vec()), ("hello".to_owned(), None));
+
+        let (lossy, encoded) = decode(vec![b'a', 0xff, b'b']);
+        assert_eq!(lossy, "a\u{fffd}b");
+        assert_eq!(encoded, Some("Yf9i".to_owned()));
+    }
 }
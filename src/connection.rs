@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use std::vec::Vec;
+
+use crate::command::{Captured, Stream};
+
+const SSH: &str = "ssh";
+const SCP: &str = "scp";
+const SUDO: &str = "sudo";
+const SH: &str = "/bin/sh";
+
+pub const SSH_HOST: &str = "ssh_host";
+pub const SSH_PORT: &str = "ssh_port";
+pub const SSH_USER: &str = "ssh_user";
+pub const SSH_PRIVATE_KEY_FILE: &str = "ssh_private_key_file";
+pub const SSH_FORWARD_AGENT: &str = "ssh_forward_agent";
+pub const SSH_HOST_KEY_CHECKING: &str = "ssh_host_key_checking";
+pub const SSH_KNOWN_HOSTS_FILE: &str = "ssh_known_hosts_file";
+pub const BECOME_USER: &str = "become_user";
+pub const CONNECTION: &str = "connection";
+
+pub trait Connection: Send + Sync {
+    fn run(
+        &self,
+        command: String,
+        args: Vec<String>,
+        output: &Stream,
+    ) -> Result<Captured, io::Error>;
+    fn put(&self, src: &Path, dest: &str) -> Result<(), io::Error>;
+    fn get(&self, src: &str, dest: &Path) -> Result<(), io::Error>;
+}
+
+pub fn from_vars(name: &str, vars: &HashMap<String, String>) -> Arc<dyn Connection> {
+    match vars.get(CONNECTION).map(|c| c.as_str()) {
+        Some("local") => Arc::new(Local),
+        _ => Arc::new(Ssh::from_vars(name, vars)),
+    }
+}
+
+#[derive(Debug)]
+pub struct Local;
+
+impl Connection for Local {
+    fn run(
+        &self,
+        command: String,
+        args: Vec<String>,
+        output: &Stream,
+    ) -> Result<Captured, io::Error> {
+        crate::command::run(command, args, output)
+    }
+
+    fn put(&self, src: &Path, dest: &str) -> Result<(), io::Error> {
+        fs::copy(src, dest).map(|_| ())
+    }
+
+    fn get(&self, src: &str, dest: &Path) -> Result<(), io::Error> {
+        fs::copy(src, dest).map(|_| ())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum HostKeyChecking {
+    Strict,
+    AcceptNew,
+    Off,
+}
+
+impl HostKeyChecking {
+    fn as_option(&self) -> &str {
+        match self {
+            HostKeyChecking::Strict => "yes",
+            HostKeyChecking::AcceptNew => "accept-new",
+            HostKeyChecking::Off => "no",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Ssh {
+    pub host: String,
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    pub private_key_file: Option<String>,
+    pub forward_agent: bool,
+    pub host_key_checking: HostKeyChecking,
+    pub known_hosts_file: Option<String>,
+    pub become_user: Option<String>,
+}
+
+impl Default for Ssh {
+    fn default() -> Self {
+        Ssh {
+            host: "".to_owned(),
+            port: None,
+            user: None,
+            private_key_file: None,
+            forward_agent: false,
+            host_key_checking: HostKeyChecking::Strict,
+            known_hosts_file: None,
+            become_user: None,
+        }
+    }
+}
+
+impl Ssh {
+    pub fn from_vars(name: &str, vars: &HashMap<String, String>) -> Self {
+        let host_key_checking = match vars.get(SSH_HOST_KEY_CHECKING).map(|s| s.as_str()) {
+            Some("accept-new") => HostKeyChecking::AcceptNew,
+            Some("no") | Some("false") | Some("off") => HostKeyChecking::Off,
+            _ => HostKeyChecking::Strict,
+        };
+        Ssh {
+            host: vars
+                .get(SSH_HOST)
+                .cloned()
+                .unwrap_or_else(|| name.to_owned()),
+            port: vars.get(SSH_PORT).and_then(|p| p.parse().ok()),
+            user: vars.get(SSH_USER).cloned(),
+            private_key_file: vars.get(SSH_PRIVATE_KEY_FILE).cloned(),
+            forward_agent: vars
+                .get(SSH_FORWARD_AGENT)
+                .map_or(false, |v| v == "true" || v == "yes"),
+            host_key_checking: host_key_checking,
+            known_hosts_file: vars.get(SSH_KNOWN_HOSTS_FILE).cloned(),
+            become_user: vars.get(BECOME_USER).cloned(),
+        }
+    }
+
+    fn options(&self) -> Vec<String> {
+        let mut options = vec![
+            "-o".to_owned(),
+            "BatchMode=yes".to_owned(),
+            "-o".to_owned(),
+            format!(
+                "StrictHostKeyChecking={}",
+                self.host_key_checking.as_option()
+            ),
+        ];
+        if let Some(known_hosts_file) = &self.known_hosts_file {
+            options.push("-o".to_owned());
+            options.push(format!("UserKnownHostsFile={}", known_hosts_file));
+        }
+        if let Some(private_key_file) = &self.private_key_file {
+            options.push("-i".to_owned());
+            options.push(private_key_file.to_owned());
+            options.push("-o".to_owned());
+            options.push("IdentitiesOnly=yes".to_owned());
+        }
+        options
+    }
+
+    fn destination(&self) -> String {
+        match &self.user {
+            Some(user) => format!("{}@{}", user, self.host),
+            None => self.host.to_owned(),
+        }
+    }
+
+    // The scp form of a remote path, where an IPv6 address must be bracketed
+    // so that its colons are not taken as the path separator.
+    fn remote_path(&self, path: &str) -> String {
+        let destination = if self.host.contains(':') && !self.host.starts_with('[') {
+            let host = format!("[{}]", self.host);
+            match &self.user {
+                Some(user) => format!("{}@{}", user, host),
+                None => host,
+            }
+        } else {
+            self.destination()
+        };
+        format!("{}:{}", destination, path)
+    }
+
+    fn remote_command(&self, command: String, args: Vec<String>) -> String {
+        let mut words = vec![];
+        if let Some(become_user) = &self.become_user {
+            words.extend(vec![
+                SUDO.to_owned(),
+                "-n".to_owned(),
+                "-H".to_owned(),
+                "-u".to_owned(),
+                become_user.to_owned(),
+                "--".to_owned(),
+            ]);
+        }
+        words.push(command);
+        words.extend(args);
+        words
+            .iter()
+            .map(|w| shell_words::quote(w).into_owned())
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    pub fn ssh_args(&self, command: String, args: Vec<String>) -> Vec<String> {
+        let mut ssh_args = self.options();
+        if self.forward_agent {
+            ssh_args.push("-A".to_owned());
+        }
+        if let Some(port) = self.port {
+            ssh_args.push("-p".to_owned());
+            ssh_args.push(port.to_string());
+        }
+        ssh_args.push(self.destination());
+        ssh_args.push("--".to_owned());
+        ssh_args.push(self.remote_command(command, args));
+        ssh_args
+    }
+
+    fn scp(&self, src: String, dest: String) -> Result<(), io::Error> {
+        let mut scp_args = self.options();
+        if let Some(port) = self.port {
+            scp_args.push("-P".to_owned());
+            scp_args.push(port.to_string());
+        }
+        scp_args.push("-q".to_owned());
+        scp_args.push(src);
+        scp_args.push(dest);
+        check(crate::command::run(
+            SCP.to_owned(),
+            scp_args,
+            &Stream::new(&self.host),
+        )?)
+    }
+
+    // Runs a remote command with its stdin and stdout connected to local
+    // files, so become_user transfers never stage data in a shared directory.
+    fn pipe(
+        &self,
+        command: String,
+        args: Vec<String>,
+        stdin: process::Stdio,
+        stdout: process::Stdio,
+    ) -> Result<(), io::Error> {
+        let output = process::Command::new(SSH)
+            .args(self.ssh_args(command, args))
+            .stdin(stdin)
+            .stdout(stdout)
+            .stderr(process::Stdio::piped())
+            .output()?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ))
+        }
+    }
+}
+
+impl Connection for Ssh {
+    fn run(
+        &self,
+        command: String,
+        args: Vec<String>,
+        output: &Stream,
+    ) -> Result<Captured, io::Error> {
+        crate::command::run(SSH.to_owned(), self.ssh_args(command, args), output)
+    }
+
+    fn put(&self, src: &Path, dest: &str) -> Result<(), io::Error> {
+        if self.become_user.is_none() {
+            let src = src.to_string_lossy().into_owned();
+            return self.scp(src, self.remote_path(dest));
+        }
+        let args = vec![
+            "-c".to_owned(),
+            "cat > \"$1\"".to_owned(),
+            SH.to_owned(),
+            dest.to_owned(),
+        ];
+        let file = fs::File::open(src)?;
+        self.pipe(SH.to_owned(), args, file.into(), process::Stdio::null())
+    }
+
+    fn get(&self, src: &str, dest: &Path) -> Result<(), io::Error> {
+        if self.become_user.is_none() {
+            let dest = dest.to_string_lossy().into_owned();
+            return self.scp(self.remote_path(src), dest);
+        }
+        let args = vec!["--".to_owned(), src.to_owned()];
+        let file = fs::File::create(dest)?;
+        let result = self.pipe("cat".to_owned(), args, process::Stdio::null(), file.into());
+        if result.is_err() {
+            let _ = fs::remove_file(dest);
+        }
+        result
+    }
+}
+
+fn check(captured: Captured) -> Result<(), io::Error> {
+    if captured.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            String::from_utf8_lossy(&captured.stderr).into_owned(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ssh_args() {
+        let mut vars = HashMap::new();
+        vars.insert(SSH_HOST.to_owned(), "10.0.0.5".to_owned());
+        vars.insert(SSH_PORT.to_owned(), "2222".to_owned());
+        vars.insert(SSH_USER.to_owned(), "admin".to_owned());
+        vars.insert(SSH_PRIVATE_KEY_FILE.to_owned(), "/keys/id".to_owned());
+        vars.insert(SSH_HOST_KEY_CHECKING.to_owned(), "accept-new".to_owned());
+        vars.insert(BECOME_USER.to_owned(), "root".to_owned());
+        let ssh = Ssh::from_vars("web1", &vars);
+
+        let args = ssh.ssh_args("echo".to_owned(), vec!["hello world".to_owned()]);
+        assert_eq!(
+            args,
+            vec![
+                "-o",
+                "BatchMode=yes",
+                "-o",
+                "StrictHostKeyChecking=accept-new",
+                "-i",
+                "/keys/id",
+                "-o",
+                "IdentitiesOnly=yes",
+                "-p",
+                "2222",
+                "admin@10.0.0.5",
+                "--",
+                "sudo -n -H -u root -- echo 'hello world'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ssh_from_vars_defaults() {
+        let ssh = Ssh::from_vars("web1", &HashMap::new());
+        assert_eq!(ssh.host, "web1");
+        assert_eq!(ssh.host_key_checking, HostKeyChecking::Strict);
+        assert_eq!(ssh.destination(), "web1");
+        assert_eq!(ssh.remote_path("/tmp/a"), "web1:/tmp/a");
+    }
+
+    #[test]
+    fn test_remote_path_ipv6() {
+        let mut vars = HashMap::new();
+        vars.insert(SSH_HOST.to_owned(), "2001:db8::5".to_owned());
+        let ssh = Ssh::from_vars("web1", &vars);
+        assert_eq!(ssh.remote_path("/tmp/a"), "[2001:db8::5]:/tmp/a");
+        assert_eq!(ssh.destination(), "2001:db8::5");
+
+        vars.insert(SSH_USER.to_owned(), "admin".to_owned());
+        let ssh = Ssh::from_vars("web1", &vars);
+        assert_eq!(ssh.remote_path("/tmp/a"), "admin@[2001:db8::5]:/tmp/a");
+    }
+
+    // The transfers themselves need a real server: sshd on localhost that
+    // accepts key authentication for the current user, with passwordless sudo.
+    #[test]
+    #[ignore]
+    fn test_ssh_loopback() {
+        let mut vars = HashMap::new();
+        vars.insert(SSH_HOST.to_owned(), "localhost".to_owned());
+        vars.insert(SSH_HOST_KEY_CHECKING.to_owned(), "accept-new".to_owned());
+        vars.insert(BECOME_USER.to_owned(), "root".to_owned());
+        let ssh = Ssh::from_vars("localhost", &vars);
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        let fetched = dir.path().join("fetched");
+        let dest = format!("/root/ferro-ssh-{}", process::id());
+        fs::write(&src, "contents").unwrap();
+
+        ssh.put(&src, &dest).unwrap();
+        ssh.get(&dest, &fetched).unwrap();
+        assert_eq!(fs::read_to_string(&fetched).unwrap(), "contents");
+        assert!(ssh.get("/root/ferro-ssh-missing", &fetched).is_err());
+        assert!(!fetched.exists());
+
+        let captured = ssh
+            .run("rm".to_owned(), vec![dest], &Stream::new("localhost"))
+            .unwrap();
+        assert!(captured.status.success());
+    }
+}
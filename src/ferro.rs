@@ -1,9 +1,29 @@
+use chrono::{DateTime, Utc};
 use serde::Serialize;
 use serde_json::value::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::default::Default;
 use std::error;
 use std::fmt;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+pub const ALWAYS: &str = "always";
+pub const NEVER: &str = "never";
+const ALL: &str = "all";
+const TAGGED: &str = "tagged";
+const UNTAGGED: &str = "untagged";
+const BLOCK: &str = "block";
+
+const NO_LOG_MESSAGE: &str = "the output has been hidden because no_log is set";
+
+pub const FAILED_TASK: &str = "failed_task";
+pub const FAILED_ERROR: &str = "failed_error";
 
 #[derive(fmt::Debug, Serialize)]
 pub struct Response {
@@ -16,6 +36,8 @@ pub struct Response {
 pub struct Error {
     pub changed: bool,
     pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<Box<dyn Output>>,
 }
 
 impl error::Error for Error {}
@@ -26,20 +48,64 @@ impl fmt::Display for Error {
     }
 }
 
-pub trait Module {
+impl From<crate::lazy::Error> for Error {
+    fn from(e: crate::lazy::Error) -> Self {
+        error(false, e.0)
+    }
+}
+
+pub trait Module: Send + Sync {
     fn name(&self) -> String;
     fn apply(&self, context: &Context) -> Result<Response, Error>;
     fn destroy(&self) -> Result<Response, Error>;
 }
 
 #[typetag::serialize(tag = "type")]
-pub trait Output: fmt::Debug {
+pub trait Output: fmt::Debug + Send + Sync {
     fn to_value(&self) -> Result<Value, serde_json::error::Error>;
 }
 
+#[derive(Clone)]
 pub struct Context {
     pub vars: HashMap<String, String>,
     pub state: HashMap<String, Value>,
+    pub previous_state: HashMap<String, Value>,
+    pub task: String,
+    pub host: Option<String>,
+    pub connection: Arc<dyn crate::connection::Connection>,
+    pub secrets: HashMap<String, crate::vault::Secret>,
+    pub no_log: bool,
+    pub stream_output: bool,
+    pub lookups: Arc<crate::lookup::Lookups>,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context {
+            vars: HashMap::new(),
+            state: HashMap::new(),
+            previous_state: HashMap::new(),
+            task: "".to_owned(),
+            host: None,
+            connection: Arc::new(crate::connection::Local),
+            secrets: HashMap::new(),
+            no_log: false,
+            stream_output: true,
+            lookups: Arc::new(Default::default()),
+        }
+    }
+}
+
+impl Context {
+    pub fn secret_values(&self) -> Vec<String> {
+        let mut values: Vec<String> = self
+            .secrets
+            .values()
+            .map(|s| s.expose().to_owned())
+            .collect();
+        values.extend(self.lookups.secrets());
+        values
+    }
 }
 
 #[derive(fmt::Debug, Serialize)]
@@ -52,6 +118,16 @@ impl Output for NullOutput {
     }
 }
 
+#[derive(fmt::Debug, Serialize)]
+pub struct RedactedOutput(Value);
+
+#[typetag::serialize]
+impl Output for RedactedOutput {
+    fn to_value(&self) -> Result<Value, serde_json::error::Error> {
+        Ok(self.0.clone())
+    }
+}
+
 #[derive(fmt::Debug)]
 pub struct NullError;
 
@@ -84,91 +160,981 @@ impl Module for NullModule {
     }
 }
 
+#[derive(Clone, Copy, fmt::Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Changed,
+    Skipped,
+    Failed,
+}
+
 #[derive(fmt::Debug, Serialize)]
 pub struct TaskResult {
+    pub task: String,
     pub module: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    pub status: Status,
     pub succeeded: bool,
     pub changed: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub output: Option<Box<dyn Output>>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub duration: f64,
+    pub rescued: bool,
+}
+
+impl TaskResult {
+    fn skipped(task: &Task, context: &Context, reason: String) -> Self {
+        let now = Utc::now();
+        TaskResult {
+            task: task.description.clone(),
+            module: task.module.name(),
+            host: context.host.clone(),
+            status: Status::Skipped,
+            succeeded: true,
+            changed: false,
+            skip_reason: Some(reason),
+            error: None,
+            output: None,
+            start: now,
+            end: now,
+            duration: 0.0,
+            rescued: false,
+        }
+    }
+
+    fn failed(task: &Task, context: &Context, description: String) -> Self {
+        let now = Utc::now();
+        TaskResult {
+            task: task.description.clone(),
+            module: task.module.name(),
+            host: context.host.clone(),
+            status: Status::Failed,
+            succeeded: false,
+            changed: false,
+            skip_reason: None,
+            error: Some(description),
+            output: None,
+            start: now,
+            end: now,
+            duration: 0.0,
+            rescued: false,
+        }
+    }
+}
+
+#[derive(fmt::Debug, Serialize)]
+pub struct PlaybookResult {
+    pub results: Vec<Box<TaskResult>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub ok: usize,
+    pub changed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub rescued: usize,
+    // Problems that did not fail the run, such as a journal that could not
+    // be written.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub duration: f64,
+}
+
+impl PlaybookResult {
+    pub fn new(results: Vec<Box<TaskResult>>, start: DateTime<Utc>, timer: Instant) -> Self {
+        let count = |f: &dyn Fn(&TaskResult) -> bool| results.iter().filter(|r| f(r)).count();
+        PlaybookResult {
+            ok: count(&|r| r.status == Status::Ok),
+            changed: count(&|r| r.status == Status::Changed),
+            failed: count(&|r| r.status == Status::Failed && !r.rescued),
+            skipped: count(&|r| r.status == Status::Skipped),
+            rescued: count(&|r| r.rescued),
+            results: results,
+            error: None,
+            warnings: vec![],
+            start: start,
+            end: Utc::now(),
+            duration: timer.elapsed().as_secs_f64(),
+        }
+    }
+
+    pub fn error(error: String, start: DateTime<Utc>, timer: Instant) -> Self {
+        let mut result = PlaybookResult::new(vec![], start, timer);
+        result.error = Some(error);
+        result
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.failed == 0 && self.error.is_none()
+    }
 }
 
 pub struct Task {
     pub description: String,
     pub module: Box<dyn Module>,
     pub when: Box<dyn crate::when::When>,
+    pub depends_on: Vec<String>,
+    pub tags: Vec<String>,
+    pub notify: Vec<String>,
+    pub block: Option<Block>,
+    pub no_log: bool,
+}
+
+pub struct Block {
+    pub tasks: Vec<Task>,
+    pub rescue: Vec<Task>,
+    pub always: Vec<Task>,
+    pub vars: HashMap<String, String>,
+    pub defaults: HashMap<String, String>,
+}
+
+impl Default for Block {
+    fn default() -> Self {
+        Block {
+            tasks: vec![],
+            rescue: vec![],
+            always: vec![],
+            vars: HashMap::new(),
+            defaults: HashMap::new(),
+        }
+    }
+}
+
+impl Default for Task {
+    fn default() -> Self {
+        Task {
+            description: "".to_owned(),
+            module: Box::new(NullModule),
+            when: Box::new(crate::when::Always),
+            depends_on: vec![],
+            tags: vec![],
+            notify: vec![],
+            block: None,
+            no_log: false,
+        }
+    }
+}
+
+fn tagged(own: &[String], tags: &[String], skip_tags: &[String]) -> bool {
+    let has = |tag: &String| {
+        own.contains(tag)
+            || (tag == TAGGED && !own.is_empty())
+            || (tag == UNTAGGED && own.is_empty())
+    };
+    if skip_tags.iter().any(|t| has(t) || t == ALL) {
+        return false;
+    }
+    if own.iter().any(|t| t == NEVER) {
+        return tags.iter().any(|t| t != ALL && t != TAGGED && has(t));
+    }
+    tags.is_empty() || own.iter().any(|t| t == ALWAYS) || tags.iter().any(|t| t == ALL || has(t))
 }
 
 impl Task {
-    pub fn run(&self, context: &Context) -> Box<TaskResult> {
-        let result = crate::when::When::when(self.when.as_ref())
-            .and_then(|proceed| {
-                if proceed {
-                    self.module
-                        .apply(context)
-                        .and_then(|response| result_response(response.changed, response.output))
-                        .map_err(|e| error(e.changed, e.description))
-                } else {
-                    result_response(false, None)
-                }
-            })
-            .map_err(|e| error(false, e.to_string()));
-
-        match result {
-            Ok(response) => Box::new(TaskResult {
-                module: self.module.name(),
-                succeeded: true,
-                changed: response.changed,
-                error: None,
-                output: response.output,
-            }),
-            Err(e) => Box::new(TaskResult {
-                module: self.module.name(),
-                succeeded: false,
-                changed: e.changed,
-                error: Some(e.description),
-                output: None,
-            }),
+    pub fn selected(&self, tags: &[String], skip_tags: &[String]) -> bool {
+        self.selected_with(&[], tags, skip_tags)
+    }
+
+    fn selected_with(&self, inherited: &[String], tags: &[String], skip_tags: &[String]) -> bool {
+        let own: Vec<String> = inherited.iter().chain(&self.tags).cloned().collect();
+        match &self.block {
+            Some(block) => block
+                .tasks
+                .iter()
+                .chain(&block.rescue)
+                .chain(&block.always)
+                .any(|t| t.selected_with(&own, tags, skip_tags)),
+            None => tagged(&own, tags, skip_tags),
         }
     }
+
+    fn contains(&self, name: &str) -> bool {
+        self.description == name
+            || self.block.as_ref().map_or(false, |block| {
+                block
+                    .tasks
+                    .iter()
+                    .chain(&block.rescue)
+                    .chain(&block.always)
+                    .any(|t| t.contains(name))
+            })
+    }
+
+    pub fn run(&self, context: &Context) -> Box<TaskResult> {
+        let start = Utc::now();
+        let timer = Instant::now();
+        let mut skip_reason = None;
+        let result = crate::when::When::when(self.when.as_ref()).and_then(|proceed| {
+            if proceed {
+                self.module.apply(context)
+            } else {
+                skip_reason = Some(self.when.reason());
+                result_response(false, None)
+            }
+        });
+
+        let (status, changed, error, output) = match result {
+            Ok(_) if skip_reason.is_some() => (Status::Skipped, false, None, None),
+            Ok(response) if response.changed => (Status::Changed, true, None, response.output),
+            Ok(response) => (Status::Ok, false, None, response.output),
+            Err(e) => (Status::Failed, e.changed, Some(e.description), e.output),
+        };
+        Box::new(TaskResult {
+            task: self.description.clone(),
+            module: self.module.name(),
+            host: context.host.clone(),
+            status: status,
+            succeeded: status != Status::Failed,
+            changed: changed,
+            skip_reason: skip_reason,
+            error: error,
+            output: output,
+            start: start,
+            end: Utc::now(),
+            duration: timer.elapsed().as_secs_f64(),
+            rescued: false,
+        })
+    }
+}
+
+#[derive(Clone, fmt::Debug, PartialEq)]
+pub enum Start {
+    Beginning,
+    Resume,
+    AtTask(String),
 }
 
 pub struct Playbook {
     pub tasks: Vec<Task>,
+    pub handlers: Vec<Task>,
     pub context: Context,
+    pub hosts: String,
+    pub limit: Option<String>,
+    pub inventory: Option<crate::inventory::Inventory>,
+    pub parallelism: usize,
+    pub reporter: Box<dyn crate::reporter::Reporter>,
+    pub state_backend: Option<Box<dyn crate::state::Backend>>,
+    pub journal: Option<PathBuf>,
+    pub start: Start,
+    pub step: bool,
+    pub tags: Vec<String>,
+    pub skip_tags: Vec<String>,
+    pub roles_path: Vec<PathBuf>,
+    pub vault: Option<crate::vault::Vault>,
+    pub vault_vars: Vec<PathBuf>,
+}
+
+impl Default for Playbook {
+    fn default() -> Self {
+        Playbook {
+            tasks: vec![],
+            handlers: vec![],
+            context: Default::default(),
+            hosts: crate::inventory::ALL.to_owned(),
+            limit: None,
+            inventory: None,
+            parallelism: 1,
+            reporter: Box::new(crate::reporter::human::Human::new(
+                Box::new(std::io::stdout()),
+                true,
+            )),
+            state_backend: None,
+            journal: None,
+            start: Start::Beginning,
+            step: false,
+            tags: vec![],
+            skip_tags: vec![],
+            roles_path: vec![PathBuf::from(crate::role::DEFAULT_ROLES_PATH)],
+            vault: None,
+            vault_vars: vec![],
+        }
+    }
 }
 
 impl Playbook {
-    pub fn run(&mut self) -> Vec<Box<TaskResult>> {
+    pub fn role(
+        &mut self,
+        name: &str,
+        params: HashMap<String, String>,
+    ) -> Result<(), crate::include::Error> {
+        let mut role = crate::role::Role::find(name, &self.roles_path)?;
+        role.params = params;
+        let (task, handlers) = role.load()?;
+        self.tasks.push(task);
+        self.handlers.extend(handlers);
+        Ok(())
+    }
+
+    pub fn include_tasks(&mut self, path: &std::path::Path) -> Result<(), crate::include::Error> {
+        self.tasks.push(crate::include::include_tasks(path)?);
+        Ok(())
+    }
+
+    pub fn import_playbook(&mut self, other: Playbook) {
+        for (name, value) in other.context.vars {
+            self.context.vars.entry(name).or_insert(value);
+        }
+        self.tasks.extend(other.tasks);
+        self.handlers.extend(other.handlers);
+    }
+
+    // The selected tasks in the order they would run, each with its depth
+    // in the blocks that contain it. A block is listed before its tasks.
+    pub fn selected_tasks(&self) -> Vec<(usize, &Task)> {
+        let mut selected = vec![];
+        self.select(&self.tasks, &[], 0, &mut selected);
+        selected
+    }
+
+    fn select<'a>(
+        &self,
+        tasks: &'a [Task],
+        inherited: &[String],
+        depth: usize,
+        selected: &mut Vec<(usize, &'a Task)>,
+    ) {
+        for task in tasks {
+            if !task.selected_with(inherited, &self.tags, &self.skip_tags) {
+                continue;
+            }
+            selected.push((depth, task));
+            if let Some(block) = &task.block {
+                let own: Vec<String> = inherited.iter().chain(&task.tags).cloned().collect();
+                for tasks in &[&block.tasks, &block.rescue, &block.always] {
+                    self.select(tasks, &own, depth + 1, selected);
+                }
+            }
+        }
+    }
+
+    pub fn all_tags(&self) -> BTreeSet<&str> {
+        fn collect<'a>(tasks: &'a [Task], tags: &mut BTreeSet<&'a str>) {
+            for task in tasks {
+                tags.extend(task.tags.iter().map(|t| t.as_str()));
+                if let Some(block) = &task.block {
+                    for tasks in &[&block.tasks, &block.rescue, &block.always] {
+                        collect(tasks, tags);
+                    }
+                }
+            }
+        }
+        let mut tags = BTreeSet::new();
+        collect(&self.tasks, &mut tags);
+        tags
+    }
+
+    pub fn run(&mut self) -> PlaybookResult {
+        let start = Utc::now();
+        let timer = Instant::now();
+        self.context.lookups.clear();
+        self.context.stream_output = self.reporter.streams_output();
+        let result = match self.load_secrets().and_then(|_| self.prepare()) {
+            Ok(mut journal) => {
+                let mut warnings = vec![];
+                let mut result = match self.run_with_state(&mut journal, &mut warnings) {
+                    Ok(results) => PlaybookResult::new(results, start, timer),
+                    Err(e) => PlaybookResult::error(e.to_string(), start, timer),
+                };
+                if result.succeeded() {
+                    if let Err(e) = journal.remove() {
+                        warnings.push(format!("unable to remove run journal: {}", e));
+                    }
+                }
+                result.warnings = warnings;
+                result
+            }
+            Err(e) => PlaybookResult::error(e, start, timer),
+        };
+        self.reporter.playbook_end(&result);
+        result
+    }
+
+    fn load_secrets(&mut self) -> Result<(), String> {
+        if self.vault_vars.is_empty() {
+            return Ok(());
+        }
+        let vault = self
+            .vault
+            .as_ref()
+            .ok_or_else(|| "a vault password or key is required".to_owned())?;
+        for path in &self.vault_vars {
+            let secrets = vault.load_vars(path).map_err(|e| e.to_string())?;
+            self.context.secrets.extend(secrets);
+        }
+        Ok(())
+    }
+
+    fn prepare(&self) -> Result<crate::journal::Journal, String> {
+        if let Start::AtTask(name) = &self.start {
+            if !self.tasks.iter().any(|t| t.contains(name)) {
+                return Err(format!("task {} not found", name));
+            }
+        }
+        for task in self.tasks.iter().chain(&self.handlers) {
+            for name in &task.notify {
+                if !self.handlers.iter().any(|h| &h.description == name) {
+                    return Err(format!(
+                        "task {} notifies unknown handler {}",
+                        task.description, name
+                    ));
+                }
+            }
+        }
+        match &self.journal {
+            Some(path) => crate::journal::Journal::load(path)
+                .map_err(|e| format!("unable to load run journal: {}", e)),
+            None => Ok(Default::default()),
+        }
+    }
+
+    fn run_with_state(
+        &mut self,
+        journal: &mut crate::journal::Journal,
+        warnings: &mut Vec<String>,
+    ) -> Result<Vec<Box<TaskResult>>, crate::state::Error> {
+        let backend = match self.state_backend.take() {
+            Some(backend) => backend,
+            None => return Ok(self.run_hosts(&mut Default::default(), journal, warnings)),
+        };
+
+        backend.lock()?;
+        let result = backend.load().and_then(|mut state| {
+            let results = self.run_hosts(&mut state, journal, warnings);
+            state.serial += 1;
+            backend.save(&state).map(|_| results)
+        });
+        let unlocked = backend.unlock();
+        self.state_backend = Some(backend);
+        let results = result?;
+        unlocked?;
+        Ok(results)
+    }
+
+    fn run_hosts(
+        &mut self,
+        state: &mut crate::state::State,
+        journal: &mut crate::journal::Journal,
+        warnings: &mut Vec<String>,
+    ) -> Vec<Box<TaskResult>> {
+        let mut runner = Runner {
+            reporter: self.reporter.as_mut(),
+            parallelism: self.parallelism,
+            step: self.step,
+            journal: journal,
+            warnings: warnings,
+            handlers: &self.handlers,
+            notified: vec![],
+            start: &self.start,
+            reached: false,
+            hidden: vec![],
+            tags: &self.tags,
+            skip_tags: &self.skip_tags,
+        };
+        let inventory = match &self.inventory {
+            Some(inventory) => inventory,
+            None => {
+                self.context.previous_state = state.host(&self.context.host);
+                let results = runner.run(&self.tasks, &mut self.context);
+                state.update(&self.context.host, runner.persisted(&self.context));
+                return results;
+            }
+        };
+
+        let limit = self.limit.as_ref().map(|limit| inventory.select(limit));
+        let hosts = inventory.select(&self.hosts).into_iter().filter(|h| {
+            limit
+                .as_ref()
+                .map_or(true, |l| l.iter().any(|l| l.name == h.name))
+        });
         let mut results = vec![];
-        for task in &self.tasks {
-            let result = task.run(&self.context);
-            if let Some(output) = result.output.as_ref() {
-                if let Ok(value) = output.to_value() {
-                    self.context.state.insert(task.description.clone(), value);
+        for host in hosts {
+            let mut vars = inventory.host_vars(&host.name);
+            vars.extend(self.context.vars.clone());
+            let mut context = Context {
+                connection: crate::connection::from_vars(&host.name, &vars),
+                previous_state: state.host(&Some(host.name.clone())),
+                host: Some(host.name.clone()),
+                vars: vars,
+                secrets: self.context.secrets.clone(),
+                stream_output: self.context.stream_output,
+                lookups: self.context.lookups.clone(),
+                ..Default::default()
+            };
+            results.extend(runner.run(&self.tasks, &mut context));
+            state.update(&context.host, runner.persisted(&context));
+        }
+        results
+    }
+}
+
+fn dependencies(tasks: &[Task]) -> Result<Vec<Vec<usize>>, (usize, String)> {
+    let mut dependencies = vec![];
+    for (index, task) in tasks.iter().enumerate() {
+        let mut indexes = vec![];
+        for dependency in &task.depends_on {
+            match tasks.iter().position(|t| &t.description == dependency) {
+                Some(i) if i == index => {
+                    return Err((index, format!("task {} depends on itself", dependency)))
+                }
+                Some(i) => indexes.push(i),
+                None => return Err((index, format!("unknown dependency {}", dependency))),
+            }
+        }
+        dependencies.push(indexes);
+    }
+
+    let mut resolved = vec![false; tasks.len()];
+    loop {
+        let ready: Vec<usize> = (0..tasks.len())
+            .filter(|i| !resolved[*i] && dependencies[*i].iter().all(|d| resolved[*d]))
+            .collect();
+        if ready.is_empty() {
+            break;
+        }
+        for i in ready {
+            resolved[i] = true;
+        }
+    }
+    match resolved.iter().position(|r| !r) {
+        Some(index) => Err((index, "dependency cycle".to_owned())),
+        None => Ok(dependencies),
+    }
+}
+
+fn redact(task: &Task, result: &mut TaskResult, context: &Context) {
+    if task.no_log {
+        result.output = None;
+        if result.error.is_some() {
+            result.error = Some(NO_LOG_MESSAGE.to_owned());
+        }
+        return;
+    }
+    let secrets = context.secret_values();
+    if secrets.is_empty() {
+        return;
+    }
+    result.error = result
+        .error
+        .as_ref()
+        .map(|e| crate::vault::redact(e, &secrets));
+    if let Some(output) = &result.output {
+        let mut value = output.to_value().unwrap_or(Value::Null);
+        crate::vault::redact_value(&mut value, &secrets);
+        result.output = Some(Box::new(RedactedOutput(value)));
+    }
+}
+
+enum Answer {
+    Yes,
+    No,
+    Continue,
+}
+
+fn confirm(task: &Task) -> Answer {
+    loop {
+        eprint!("Perform task: {} (N)o/(y)es/(c)ontinue: ", task.description);
+        let _ = io::stderr().flush();
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer).unwrap_or(0) == 0 {
+            return Answer::No;
+        }
+        match answer.trim().to_lowercase().as_str() {
+            "" | "n" | "no" => return Answer::No,
+            "y" | "yes" => return Answer::Yes,
+            "c" | "continue" => return Answer::Continue,
+            _ => continue,
+        }
+    }
+}
+
+struct Runner<'a> {
+    reporter: &'a mut dyn crate::reporter::Reporter,
+    parallelism: usize,
+    step: bool,
+    journal: &'a mut crate::journal::Journal,
+    warnings: &'a mut Vec<String>,
+    handlers: &'a [Task],
+    notified: Vec<String>,
+    start: &'a Start,
+    reached: bool,
+    hidden: Vec<String>,
+    tags: &'a [String],
+    skip_tags: &'a [String],
+}
+
+impl<'a> Runner<'a> {
+    fn skips(
+        &self,
+        tasks: &[Task],
+        inherited: &[String],
+        context: &Context,
+    ) -> Vec<Option<String>> {
+        let entry = match self.start {
+            Start::Beginning => None,
+            _ => self.journal.entry(&context.host),
+        };
+        let completed = match self.start {
+            Start::Resume => entry.map_or(vec![], |e| e.completed.clone()),
+            _ => vec![],
+        };
+        // A task whose outputs could not be restored runs again, along with
+        // any list that contains it, so that later tasks can read them.
+        let redacted = entry.map_or(vec![], |e| e.redacted.clone());
+        let rerun = |task: &Task| redacted.iter().any(|r| task.contains(r));
+        // Until the start task is reached, lists that do not contain it are
+        // skipped entirely.
+        let position = match self.start {
+            Start::AtTask(name) if !self.reached => Some((
+                tasks
+                    .iter()
+                    .position(|t| t.contains(name))
+                    .unwrap_or_else(|| tasks.len()),
+                name,
+            )),
+            _ => None,
+        };
+        tasks
+            .iter()
+            .enumerate()
+            .map(|(index, task)| {
+                if completed.contains(&task.description) && !rerun(task) {
+                    Some("completed in a previous run".to_owned())
+                } else if let Some((_, name)) = position.filter(|(p, _)| index < *p && !rerun(task))
+                {
+                    Some(format!("starting at task {}", name))
+                } else if !task.selected_with(inherited, self.tags, self.skip_tags) {
+                    Some("excluded by tags".to_owned())
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn skip(&mut self, task: &Task, skip: &Option<String>) -> Option<String> {
+        if skip.is_some() {
+            return skip.clone();
+        }
+        if !self.step {
+            return None;
+        }
+        match confirm(task) {
+            Answer::Yes => None,
+            Answer::No => Some("declined at step prompt".to_owned()),
+            Answer::Continue => {
+                self.step = false;
+                None
+            }
+        }
+    }
+
+    fn finish(&mut self, task: &Task, result: &TaskResult, context: &mut Context) {
+        if let (false, Some(output)) = (result.status == Status::Skipped, &result.output) {
+            if let Ok(value) = output.to_value() {
+                context.state.insert(task.description.clone(), value);
+            }
+        }
+        if task.no_log && !self.hidden.contains(&task.description) {
+            self.hidden.push(task.description.clone());
+        }
+        let persisted = self.persisted(context);
+        let redacted = persisted.get(&task.description) != context.state.get(&task.description);
+        if let Err(e) = self.journal.record(result, &persisted, redacted) {
+            let warning = format!("unable to write run journal: {}", e);
+            if !self.warnings.contains(&warning) {
+                self.warnings.push(warning);
+            }
+        }
+        if result.changed && result.succeeded {
+            for name in &task.notify {
+                if !self.notified.contains(name) {
+                    self.notified.push(name.clone());
                 }
             }
+        }
+    }
 
-            println!("{}", serde_json::to_string_pretty(&result).unwrap());
+    // The outputs that may be written to the journal or the state backend:
+    // no_log tasks are left out and secrets are redacted.
+    fn persisted(&self, context: &Context) -> HashMap<String, Value> {
+        let secrets = context.secret_values();
+        context
+            .state
+            .iter()
+            .filter(|(task, _)| !self.hidden.contains(task))
+            .map(|(task, value)| {
+                let mut value = value.clone();
+                crate::vault::redact_value(&mut value, &secrets);
+                (task.clone(), value)
+            })
+            .collect()
+    }
 
-            let succeeded = result.succeeded;
+    fn flush(&mut self, context: &mut Context) -> Vec<Box<TaskResult>> {
+        let mut results = vec![];
+        let handlers = self.handlers;
+        while let Some(handler) = handlers
+            .iter()
+            .find(|h| self.notified.contains(&h.description))
+        {
+            self.notified.retain(|n| n != &handler.description);
+            let mut handler_context = context.clone();
+            handler_context.task = handler.description.clone();
+            handler_context.no_log = handler.no_log;
+            self.reporter.task_start(handler, &handler_context);
+            let mut result = handler.run(&handler_context);
+            self.finish(handler, &result, context);
+            redact(handler, &mut result, context);
+            self.reporter.task_end(handler, &result);
+            let failed = !result.succeeded;
             results.push(result);
-            if succeeded {
-                continue;
-            } else {
-                break;
+            if failed {
+                self.notified.clear();
             }
         }
         results
     }
+
+    fn block(
+        &mut self,
+        task: &Task,
+        block: &Block,
+        inherited: &[String],
+        context: &mut Context,
+    ) -> (Vec<Box<TaskResult>>, Box<TaskResult>) {
+        let start = Utc::now();
+        let timer = Instant::now();
+        let mut result = match task.when.when() {
+            Ok(true) => None,
+            Ok(false) => Some(TaskResult::skipped(task, context, task.when.reason())),
+            Err(e) => Some(TaskResult::failed(task, context, e.description)),
+        };
+        if let Some(mut result) = result.take() {
+            result.module = BLOCK.to_owned();
+            return (vec![], Box::new(result));
+        }
+
+        let tags: Vec<String> = inherited.iter().chain(&task.tags).cloned().collect();
+        let mut block_context = context.clone();
+        for (name, value) in &block.defaults {
+            block_context
+                .vars
+                .entry(name.clone())
+                .or_insert_with(|| value.clone());
+        }
+        block_context.vars.extend(block.vars.clone());
+        let (mut results, mut failed) = self.run_tasks(&block.tasks, &tags, &mut block_context);
+        if failed && !block.rescue.is_empty() {
+            if let Some(failure) = results.iter().find(|r| !r.succeeded) {
+                block_context
+                    .vars
+                    .insert(FAILED_TASK.to_owned(), failure.task.clone());
+                block_context.vars.insert(
+                    FAILED_ERROR.to_owned(),
+                    failure.error.clone().unwrap_or_default(),
+                );
+            }
+            let (rescue, rescue_failed) = self.run_tasks(&block.rescue, &tags, &mut block_context);
+            if !rescue_failed {
+                for result in results.iter_mut().filter(|r| !r.succeeded) {
+                    result.rescued = true;
+                }
+            }
+            results.extend(rescue);
+            failed = rescue_failed;
+        }
+        let (always, always_failed) = self.run_tasks(&block.always, &tags, &mut block_context);
+        results.extend(always);
+        failed = failed || always_failed;
+        context.state = block_context.state;
+
+        let changed = results.iter().any(|r| r.changed);
+        let error = results
+            .iter()
+            .rev()
+            .find(|r| !r.succeeded && !r.rescued)
+            .filter(|_| failed)
+            .map(|r| format!("task {} failed", r.task));
+        let status = if failed {
+            Status::Failed
+        } else if changed {
+            Status::Changed
+        } else {
+            Status::Ok
+        };
+        let result = Box::new(TaskResult {
+            task: task.description.clone(),
+            module: BLOCK.to_owned(),
+            host: context.host.clone(),
+            status: status,
+            succeeded: !failed,
+            changed: changed,
+            skip_reason: None,
+            error: error,
+            output: None,
+            start: start,
+            end: Utc::now(),
+            duration: timer.elapsed().as_secs_f64(),
+            rescued: false,
+        });
+        (results, result)
+    }
+
+    fn run(&mut self, tasks: &[Task], context: &mut Context) -> Vec<Box<TaskResult>> {
+        if let (false, Some(entry)) = (
+            self.start == &Start::Beginning,
+            self.journal.entry(&context.host),
+        ) {
+            context.state.extend(
+                entry
+                    .state
+                    .iter()
+                    .filter(|(task, _)| !entry.redacted.contains(task))
+                    .map(|(task, value)| (task.clone(), value.clone())),
+            );
+        }
+        self.journal
+            .begin(&context.host, self.start == &Start::Resume);
+        self.notified.clear();
+        self.reached = false;
+        let (mut results, failed) = self.run_tasks(tasks, &[], context);
+        if !failed {
+            results.extend(self.flush(context));
+        }
+        results
+    }
+
+    fn run_tasks(
+        &mut self,
+        tasks: &[Task],
+        inherited: &[String],
+        context: &mut Context,
+    ) -> (Vec<Box<TaskResult>>, bool) {
+        let dependencies = match dependencies(tasks) {
+            Ok(dependencies) => dependencies,
+            Err((index, description)) => {
+                let result = Box::new(TaskResult::failed(&tasks[index], context, description));
+                self.reporter.task_end(&tasks[index], &result);
+                return (vec![result], true);
+            }
+        };
+
+        let skips = self.skips(tasks, inherited, context);
+        let flushes: Vec<usize> = (0..tasks.len())
+            .filter(|i| tasks[*i].module.name() == crate::modules::meta::FLUSH_HANDLERS)
+            .collect();
+        let mut nested: Vec<Vec<Box<TaskResult>>> = tasks.iter().map(|_| vec![]).collect();
+        let mut results: Vec<Option<Box<TaskResult>>> = tasks.iter().map(|_| None).collect();
+        let mut started = vec![false; tasks.len()];
+        let mut running = 0;
+        let mut reported = 0;
+        let mut failed = false;
+        let (sender, receiver) = mpsc::channel();
+
+        thread::scope(|scope| loop {
+            let mut finished = vec![];
+            for index in 0..tasks.len() {
+                if failed || running >= self.parallelism.max(1) {
+                    break;
+                }
+                let flush = flushes.contains(&index);
+                let ready = dependencies[index]
+                    .iter()
+                    .all(|d| results[*d].as_ref().map_or(false, |r| r.succeeded))
+                    && flushes.iter().all(|f| *f >= index || results[*f].is_some())
+                    && (!flush || results[..index].iter().all(|r| r.is_some()));
+                if started[index] || !ready {
+                    continue;
+                }
+                started[index] = true;
+
+                let task = &tasks[index];
+                let mut task_context = context.clone();
+                task_context.task = task.description.clone();
+                task_context.no_log = task.no_log;
+                if let Some(reason) = self.skip(task, &skips[index]) {
+                    finished.push((index, Box::new(TaskResult::skipped(task, context, reason))));
+                    continue;
+                }
+                if let Start::AtTask(name) = self.start {
+                    self.reached = self.reached || &task.description == name;
+                }
+                if let Some(block) = &task.block {
+                    self.reporter.task_start(task, &task_context);
+                    let (results, result) = self.block(task, block, inherited, context);
+                    nested[index] = results;
+                    finished.push((index, result));
+                    continue;
+                }
+                if flush {
+                    self.reporter.task_start(task, &task_context);
+                    nested[index] = self.flush(context);
+                    failed = failed || nested[index].iter().any(|r| !r.succeeded);
+                    finished.push((index, task.run(&task_context)));
+                    continue;
+                }
+                running += 1;
+                self.reporter.task_start(task, &task_context);
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    let _ = sender.send((index, task.run(&task_context)));
+                });
+            }
+            if finished.is_empty() {
+                if running == 0 {
+                    break;
+                }
+                finished.push(receiver.recv().unwrap());
+                running -= 1;
+            }
+
+            for (index, mut result) in finished {
+                self.finish(&tasks[index], &result, context);
+                redact(&tasks[index], &mut result, context);
+                failed = failed || !result.succeeded;
+                results[index] = Some(result);
+            }
+
+            while reported < tasks.len() && (results[reported].is_some() || !started[reported]) {
+                if let Some(result) = &results[reported] {
+                    self.reporter.task_end(&tasks[reported], result);
+                } else if !failed {
+                    break;
+                }
+                reported += 1;
+            }
+        });
+
+        let results = results
+            .into_iter()
+            .zip(nested)
+            // A block's own result is reported but not returned alongside
+            // its tasks, so the tasks are not counted twice.
+            .flat_map(|(result, nested)| {
+                result
+                    .filter(|r| r.module != BLOCK || nested.is_empty())
+                    .into_iter()
+                    .chain(nested)
+            })
+            .collect();
+        (results, failed)
+    }
 }
 
 pub fn error(changed: bool, description: String) -> Error {
     Error {
         changed: changed,
         description: description,
+        output: None,
     }
 }
 
@@ -176,6 +1142,19 @@ pub fn result_error(changed: bool, description: String) -> Result<Response, Erro
     Err(Error {
         changed: changed,
         description: description,
+        output: None,
+    })
+}
+
+pub fn result_error_with_output(
+    changed: bool,
+    description: String,
+    output: Box<dyn Output>,
+) -> Result<Response, Error> {
+    Err(Error {
+        changed: changed,
+        description: description,
+        output: Some(output),
     })
 }
 
@@ -236,6 +1215,27 @@ mod tests {
     use serde_json::json;
     use std::fs;
 
+    #[test]
+    fn test_task_selected() {
+        let task = |tags: &[&str]| crate::ferro::Task {
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        };
+        let tags = |tags: &[&str]| -> Vec<String> { tags.iter().map(|t| t.to_string()).collect() };
+
+        assert!(task(&[]).selected(&[], &[]));
+        assert!(task(&["deploy"]).selected(&tags(&["deploy"]), &[]));
+        assert!(!task(&["deploy"]).selected(&tags(&["setup"]), &[]));
+        assert!(!task(&["deploy"]).selected(&[], &tags(&["deploy"])));
+        assert!(task(&["always"]).selected(&tags(&["setup"]), &[]));
+        assert!(!task(&["always"]).selected(&[], &tags(&["always"])));
+        assert!(!task(&["never", "debug"]).selected(&[], &[]));
+        assert!(!task(&["never", "debug"]).selected(&tags(&["all"]), &[]));
+        assert!(task(&["never", "debug"]).selected(&tags(&["debug"]), &[]));
+        assert!(task(&[]).selected(&tags(&["untagged"]), &[]));
+        assert!(!task(&["deploy"]).selected(&[], &tags(&["tagged"])));
+    }
+
     #[test]
     fn test_find() {
         let value = json!({
@@ -273,6 +1273,607 @@ mod tests {
         assert_eq!(found_obj_4, json!({"k1": "v1", "k2": "v2"}));
     }
 
+    #[test]
+    fn test_task_failure_output() {
+        let task = crate::ferro::Task {
+            description: "fail".to_owned(),
+            module: Box::new(crate::modules::shell::Shell {
+                script: Box::new(crate::lazy::string("echo partial; exit 2".to_owned())),
+                ..Default::default()
+            }),
+            when: Box::new(crate::when::Always),
+            ..Default::default()
+        };
+        let result = task.run(&Default::default());
+        assert!(!result.succeeded);
+
+        let value = result.output.unwrap().to_value().unwrap();
+        assert_eq!(find("exit_status", &value).unwrap(), 2);
+        assert_eq!(find("stdout", &value).unwrap(), "partial\n");
+    }
+
+    #[test]
+    fn test_playbook_inventory() {
+        let inventory = crate::inventory::file::parse_ini(
+            "[web]\nweb1 connection=local\nweb2 connection=local\ndb1 connection=local\n",
+        )
+        .unwrap();
+        let mut playbook = crate::ferro::Playbook {
+            tasks: vec![crate::ferro::Task {
+                description: "echo host".to_owned(),
+                module: Box::new(crate::modules::command::Command {
+                    command: Box::new(crate::lazy::string("/bin/echo".to_owned())),
+                    args: Box::new(|_| {
+                        Ok(vec![Box::new(crate::lazy::var(
+                            crate::inventory::INVENTORY_HOSTNAME.to_owned(),
+                        ))])
+                    }),
+                    ..Default::default()
+                }),
+                when: Box::new(crate::when::Always),
+                ..Default::default()
+            }],
+            hosts: "web:!db1".to_owned(),
+            inventory: Some(inventory),
+            ..Default::default()
+        };
+
+        let results = playbook.run().results;
+        let hosts: Vec<Option<String>> = results.iter().map(|r| r.host.clone()).collect();
+        assert_eq!(
+            hosts,
+            vec![Some("web1".to_owned()), Some("web2".to_owned())]
+        );
+        let value = results[1].output.as_ref().unwrap().to_value().unwrap();
+        assert_eq!(find("stdout", &value).unwrap(), "web2\n");
+
+        playbook.limit = Some("web2,db1".to_owned());
+        let results = playbook.run().results;
+        let hosts: Vec<Option<String>> = results.iter().map(|r| r.host.clone()).collect();
+        assert_eq!(hosts, vec![Some("web2".to_owned())]);
+    }
+
+    #[test]
+    fn test_playbook_skipped() {
+        let echo = |when: Box<dyn crate::when::When>| crate::ferro::Task {
+            description: "echo".to_owned(),
+            module: Box::new(crate::modules::shell::Shell {
+                script: Box::new(crate::lazy::string("echo ran".to_owned())),
+                ..Default::default()
+            }),
+            when: when,
+            ..Default::default()
+        };
+        let mut playbook = crate::ferro::Playbook {
+            tasks: vec![
+                echo(Box::new(crate::when::Always)),
+                echo(Box::new(crate::when::when_execute("/bin/false"))),
+            ],
+            ..Default::default()
+        };
+
+        let result = playbook.run();
+        assert_eq!(result.changed, 1);
+        assert_eq!(result.skipped, 1);
+        assert_eq!(result.results[1].status, Status::Skipped);
+        assert_eq!(
+            result.results[1].skip_reason,
+            Some("`/bin/false` exited with a non-zero status".to_owned())
+        );
+        let value = &playbook.context.state["echo"];
+        assert_eq!(find("stdout", value).unwrap(), "ran\n");
+    }
+
+    #[test]
+    fn test_playbook_state_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let playbook = |when: Box<dyn crate::when::When>| crate::ferro::Playbook {
+            tasks: vec![
+                crate::ferro::Task {
+                    description: "create".to_owned(),
+                    module: Box::new(crate::modules::shell::Shell {
+                        script: Box::new(crate::lazy::string("echo created".to_owned())),
+                        ..Default::default()
+                    }),
+                    when: when,
+                    ..Default::default()
+                },
+                crate::ferro::Task {
+                    description: "read".to_owned(),
+                    module: Box::new(crate::modules::command::Command {
+                        command: Box::new(crate::lazy::string("/bin/echo".to_owned())),
+                        args: Box::new(|_| {
+                            Ok(vec![
+                                Box::new(crate::lazy::state(
+                                    "create".to_owned(),
+                                    "stdout".to_owned(),
+                                )),
+                                Box::new(crate::lazy::previous_state(
+                                    "create".to_owned(),
+                                    "stdout".to_owned(),
+                                )),
+                            ])
+                        }),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ],
+            state_backend: Some(Box::new(crate::state::file::File { path: path.clone() })),
+            ..Default::default()
+        };
+
+        assert!(playbook(Box::new(crate::when::Always)).run().succeeded());
+
+        let mut second = playbook(Box::new(crate::when::Never));
+        let result = second.run();
+        assert!(result.succeeded());
+        assert_eq!(result.skipped, 1);
+        assert!(!second.context.state.contains_key("create"));
+        let value = &second.context.state["read"];
+        assert_eq!(find("stdout", value).unwrap(), " created\n\n");
+
+        let mut locked = playbook(Box::new(crate::when::Never));
+        let lock = crate::state::file::File { path: path.clone() };
+        crate::state::Backend::lock(&lock).unwrap();
+        assert!(locked.run().error.unwrap().contains("locked"));
+        crate::state::Backend::unlock(&lock).unwrap();
+    }
+
+    #[test]
+    fn test_playbook_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.json");
+        let marker = dir.path().join("marker");
+        let playbook = |start: crate::ferro::Start| crate::ferro::Playbook {
+            tasks: vec![
+                crate::ferro::Task {
+                    description: "first".to_owned(),
+                    module: Box::new(crate::modules::shell::Shell {
+                        script: Box::new(crate::lazy::string("echo first".to_owned())),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                crate::ferro::Task {
+                    description: "second".to_owned(),
+                    module: Box::new(crate::modules::shell::Shell {
+                        script: Box::new(crate::lazy::string(format!(
+                            "test -f {}",
+                            marker.display()
+                        ))),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                crate::ferro::Task {
+                    description: "third".to_owned(),
+                    module: Box::new(crate::modules::command::Command {
+                        command: Box::new(crate::lazy::string("/bin/echo".to_owned())),
+                        args: Box::new(|_| {
+                            Ok(vec![Box::new(crate::lazy::state(
+                                "first".to_owned(),
+                                "stdout".to_owned(),
+                            ))])
+                        }),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ],
+            journal: Some(path.clone()),
+            start: start,
+            ..Default::default()
+        };
+
+        let result = playbook(crate::ferro::Start::Beginning).run();
+        assert!(!result.succeeded());
+        assert!(path.exists());
+
+        fs::write(&marker, "").unwrap();
+        let mut resumed = playbook(crate::ferro::Start::Resume);
+        let result = resumed.run();
+        assert!(result.succeeded());
+        assert_eq!(result.skipped, 1);
+        assert_eq!(
+            result.results[0].skip_reason,
+            Some("completed in a previous run".to_owned())
+        );
+        let value = &resumed.context.state["third"];
+        assert_eq!(find("stdout", value).unwrap(), "first\n\n");
+        assert!(!path.exists());
+
+        let result = playbook(crate::ferro::Start::AtTask("third".to_owned())).run();
+        assert_eq!(result.skipped, 2);
+        assert!(playbook(crate::ferro::Start::AtTask("missing".to_owned()))
+            .run()
+            .error
+            .is_some());
+    }
+
+    #[test]
+    fn test_playbook_list() {
+        let tagged = |description: &str, tags: &[&str]| crate::ferro::Task {
+            description: description.to_owned(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        };
+        let playbook = crate::ferro::Playbook {
+            tasks: vec![
+                tagged("setup", &["setup"]),
+                crate::ferro::Task {
+                    description: "role web".to_owned(),
+                    tags: vec!["web".to_owned()],
+                    block: Some(crate::ferro::Block {
+                        tasks: vec![tagged("install", &["packages"]), tagged("configure", &[])],
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ],
+            tags: vec!["web".to_owned()],
+            ..Default::default()
+        };
+        let listed: Vec<(usize, &str)> = playbook
+            .selected_tasks()
+            .into_iter()
+            .map(|(depth, task)| (depth, task.description.as_str()))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![(0, "role web"), (1, "install"), (1, "configure")]
+        );
+        assert_eq!(
+            playbook.all_tags().into_iter().collect::<Vec<&str>>(),
+            vec!["packages", "setup", "web"]
+        );
+    }
+
+    #[test]
+    fn test_playbook_journal_warnings() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut playbook = crate::ferro::Playbook {
+            tasks: vec![echo_task("first", vec![])],
+            journal: Some(dir.path().join("missing").join("journal.json")),
+            ..Default::default()
+        };
+        let result = playbook.run();
+        assert!(result.succeeded());
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].starts_with("unable to write run journal"));
+    }
+
+    #[test]
+    fn test_playbook_resume_redacted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.json");
+        let marker = dir.path().join("marker");
+        let playbook = |start: crate::ferro::Start| crate::ferro::Playbook {
+            tasks: vec![
+                crate::ferro::Task {
+                    description: "token".to_owned(),
+                    module: Box::new(crate::modules::shell::Shell {
+                        script: Box::new(crate::lazy::string("echo token".to_owned())),
+                        ..Default::default()
+                    }),
+                    no_log: true,
+                    ..Default::default()
+                },
+                crate::ferro::Task {
+                    description: "wait".to_owned(),
+                    module: Box::new(crate::modules::shell::Shell {
+                        script: Box::new(crate::lazy::string(format!(
+                            "test -f {}",
+                            marker.display()
+                        ))),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                crate::ferro::Task {
+                    description: "use".to_owned(),
+                    module: Box::new(crate::modules::command::Command {
+                        command: Box::new(crate::lazy::string("/bin/echo".to_owned())),
+                        args: Box::new(|_| {
+                            Ok(vec![Box::new(crate::lazy::state(
+                                "token".to_owned(),
+                                "stdout".to_owned(),
+                            ))])
+                        }),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ],
+            journal: Some(path.clone()),
+            start: start,
+            ..Default::default()
+        };
+
+        assert!(!playbook(crate::ferro::Start::Beginning).run().succeeded());
+        fs::write(&marker, "").unwrap();
+        let mut resumed = playbook(crate::ferro::Start::Resume);
+        let result = resumed.run();
+        assert!(result.succeeded());
+        assert_eq!(result.skipped, 0);
+        let value = &resumed.context.state["use"];
+        assert_eq!(find("stdout", value).unwrap(), "token\n\n");
+
+        fs::remove_file(&marker).unwrap();
+        assert!(!playbook(crate::ferro::Start::Beginning).run().succeeded());
+        fs::write(&marker, "").unwrap();
+        let mut started = playbook(crate::ferro::Start::AtTask("use".to_owned()));
+        let result = started.run();
+        assert!(result.succeeded());
+        assert_eq!(result.skipped, 1);
+        let value = &started.context.state["use"];
+        assert_eq!(find("stdout", value).unwrap(), "token\n\n");
+    }
+
+    fn sleep_task(description: &str, depends_on: Vec<&str>) -> crate::ferro::Task {
+        crate::ferro::Task {
+            description: description.to_owned(),
+            module: Box::new(crate::modules::shell::Shell {
+                script: Box::new(crate::lazy::string("sleep 1".to_owned())),
+                ..Default::default()
+            }),
+            depends_on: depends_on.into_iter().map(|d| d.to_owned()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_playbook_parallel() {
+        let mut playbook = crate::ferro::Playbook {
+            tasks: vec![
+                sleep_task("first", vec![]),
+                sleep_task("second", vec![]),
+                sleep_task("third", vec!["first", "second"]),
+            ],
+            parallelism: 2,
+            ..Default::default()
+        };
+
+        let result = playbook.run();
+        assert_eq!(result.results.len(), 3);
+        assert_eq!(result.changed, 3);
+        assert!(result.succeeded());
+
+        let task = |description: &str| {
+            result
+                .results
+                .iter()
+                .find(|r| r.task == description)
+                .unwrap()
+        };
+        let (first, second, third) = (task("first"), task("second"), task("third"));
+        assert!(first.start < second.end && second.start < first.end);
+        assert!(third.start >= first.end && third.start >= second.end);
+    }
+
+    #[test]
+    fn test_playbook_state_dependencies() {
+        let mut playbook = crate::ferro::Playbook {
+            tasks: vec![
+                crate::ferro::Task {
+                    description: "first".to_owned(),
+                    module: Box::new(crate::modules::shell::Shell {
+                        script: Box::new(crate::lazy::string(
+                            "sleep 1; printf /bin/true".to_owned(),
+                        )),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                crate::ferro::Task {
+                    description: "second".to_owned(),
+                    module: Box::new(crate::modules::command::Command {
+                        command: Box::new(crate::lazy::state(
+                            "first".to_owned(),
+                            "stdout".to_owned(),
+                        )),
+                        ..Default::default()
+                    }),
+                    depends_on: vec!["first".to_owned()],
+                    ..Default::default()
+                },
+            ],
+            parallelism: 2,
+            ..Default::default()
+        };
+
+        let result = playbook.run();
+        assert!(result.succeeded());
+        assert!(result.results[1].start >= result.results[0].end);
+
+        let tasks = vec![sleep_task("loop", vec!["loop"])];
+        assert_eq!(
+            dependencies(&tasks).unwrap_err(),
+            (0, "task loop depends on itself".to_owned())
+        );
+    }
+
+    fn echo_task(description: &str, notify: Vec<&str>) -> crate::ferro::Task {
+        crate::ferro::Task {
+            description: description.to_owned(),
+            module: Box::new(crate::modules::shell::Shell {
+                script: Box::new(crate::lazy::string(format!("echo {}", description))),
+                ..Default::default()
+            }),
+            notify: notify.into_iter().map(|n| n.to_owned()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_playbook_handlers() {
+        let mut playbook = crate::ferro::Playbook {
+            tasks: vec![
+                echo_task("config", vec!["restart"]),
+                echo_task("unit", vec!["restart"]),
+                crate::ferro::Task {
+                    description: "flush".to_owned(),
+                    module: Box::new(crate::modules::meta::FlushHandlers),
+                    ..Default::default()
+                },
+                echo_task("cert", vec!["reload"]),
+            ],
+            handlers: vec![
+                echo_task("reload", vec![]),
+                echo_task("restart", vec!["reload"]),
+                echo_task("unused", vec![]),
+            ],
+            ..Default::default()
+        };
+
+        let result = playbook.run();
+        assert!(result.succeeded());
+        let order: Vec<&str> = result.results.iter().map(|r| r.task.as_str()).collect();
+        assert_eq!(
+            order,
+            vec!["config", "unit", "flush", "restart", "reload", "cert", "reload"]
+        );
+
+        playbook.tasks.push(echo_task("bogus", vec!["missing"]));
+        assert!(playbook.run().error.unwrap().contains("missing"));
+    }
+
+    #[test]
+    fn test_playbook_block() {
+        let failing = || crate::ferro::Task {
+            description: "fail".to_owned(),
+            module: Box::new(crate::modules::shell::Shell {
+                script: Box::new(crate::lazy::string("echo $STAGE; exit 1".to_owned())),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let report = crate::ferro::Task {
+            description: "report".to_owned(),
+            module: Box::new(crate::modules::command::Command {
+                command: Box::new(crate::lazy::string("/bin/echo".to_owned())),
+                args: Box::new(|_| {
+                    Ok(vec![
+                        Box::new(crate::lazy::var(crate::ferro::FAILED_TASK.to_owned())),
+                        Box::new(crate::lazy::var("stage".to_owned())),
+                    ])
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut playbook = crate::ferro::Playbook {
+            tasks: vec![crate::ferro::Task {
+                description: "deploy".to_owned(),
+                block: Some(crate::ferro::Block {
+                    tasks: vec![failing(), echo_task("not reached", vec![])],
+                    rescue: vec![report],
+                    always: vec![echo_task("cleanup", vec![])],
+                    vars: vec![("stage".to_owned(), "prod".to_owned())]
+                        .into_iter()
+                        .collect(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let result = playbook.run();
+        assert!(result.succeeded());
+        assert_eq!(result.rescued, 1);
+        let order: Vec<&str> = result.results.iter().map(|r| r.task.as_str()).collect();
+        assert_eq!(order, vec!["fail", "report", "cleanup"]);
+        let value = &playbook.context.state["report"];
+        assert_eq!(find("stdout", value).unwrap(), "fail prod\n");
+
+        playbook.tasks[0].block.as_mut().unwrap().rescue = vec![failing()];
+        let result = playbook.run();
+        assert!(!result.succeeded());
+        assert_eq!(result.failed, 2);
+        assert!(playbook.context.state.contains_key("cleanup"));
+
+        playbook.tasks.insert(0, echo_task("setup", vec![]));
+        playbook.start = crate::ferro::Start::AtTask("cleanup".to_owned());
+        let result = playbook.run();
+        let ran: Vec<&str> = result
+            .results
+            .iter()
+            .filter(|r| r.status != crate::ferro::Status::Skipped)
+            .map(|r| r.task.as_str())
+            .collect();
+        assert_eq!(ran, vec!["cleanup"]);
+        assert!(result.succeeded());
+    }
+
+    #[test]
+    fn test_playbook_secrets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.yml");
+        let journal = dir.path().join("journal.json");
+        let state = dir.path().join("state.json");
+        let vault = crate::vault::Vault::from_password(crate::vault::Secret::new("pw".to_owned()));
+        fs::write(&path, vault.encrypt(b"token: s3cret\n").unwrap()).unwrap();
+        let leak = |description: &str, no_log: bool| crate::ferro::Task {
+            description: description.to_owned(),
+            module: Box::new(crate::modules::shell::Shell {
+                script: Box::new(crate::lazy::template("echo {{ token }}; exit 1".to_owned())),
+                ..Default::default()
+            }),
+            no_log: no_log,
+            ..Default::default()
+        };
+        let mut playbook = crate::ferro::Playbook {
+            tasks: vec![leak("leak", false)],
+            vault: Some(vault),
+            vault_vars: vec![path.clone()],
+            journal: Some(journal.clone()),
+            state_backend: Some(Box::new(crate::state::file::File {
+                path: state.clone(),
+            })),
+            ..Default::default()
+        };
+
+        let result = playbook.run();
+        let serialized = serde_json::to_string(&result).unwrap();
+        assert!(!serialized.contains("s3cret"));
+        assert!(serialized.contains("********"));
+        for persisted in &[&journal, &state] {
+            let contents = fs::read_to_string(persisted).unwrap();
+            assert!(!contents.contains("s3cret"));
+            assert!(contents.contains("********"));
+        }
+
+        playbook.tasks = vec![leak("hidden", true)];
+        let result = playbook.run();
+        assert!(result.results[0].output.is_none());
+        assert_eq!(result.results[0].error, Some(NO_LOG_MESSAGE.to_owned()));
+        assert!(playbook.context.state.contains_key("hidden"));
+        for persisted in &[&journal, &state] {
+            let contents = fs::read_to_string(persisted).unwrap();
+            assert!(!contents.contains("\"hidden\": {"));
+            assert!(!contents.contains("s3cret"));
+        }
+
+        playbook.vault = None;
+        assert!(playbook.run().error.is_some());
+    }
+
+    #[test]
+    fn test_playbook_dependency_cycle() {
+        let mut playbook = crate::ferro::Playbook {
+            tasks: vec![
+                sleep_task("first", vec!["second"]),
+                sleep_task("second", vec!["first"]),
+            ],
+            ..Default::default()
+        };
+        let results = playbook.run().results;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].error, Some("dependency cycle".to_owned()));
+    }
+
     #[test]
     fn test_playbook() {
         let _ = fs::read_to_string("cf.yml")
@@ -283,25 +1884,39 @@ mod tests {
                         description: "do nothing".to_owned(),
                         module: Box::new(crate::ferro::NullModule),
                         when: Box::new(crate::when::Never),
+                        ..Default::default()
                     },
                     crate::ferro::Task {
                         description: "do nothing again".to_owned(),
                         module: Box::new(crate::ferro::NullModule),
                         when: Box::new(crate::when::Always),
+                        ..Default::default()
                     },
                     crate::ferro::Task {
                         description: "run ls".to_owned(),
                         module: Box::new(crate::modules::command::Command {
                             command: Box::new(crate::lazy::string("/bin/ls".to_owned())),
                             args: Box::new(|_| {
-                                vec![
+                                Ok(vec![
                                     Box::new(crate::lazy::string("-l".to_owned())),
                                     Box::new(crate::lazy::string("/".to_owned())),
-                                ]
+                                ])
                             }),
                             ..Default::default()
                         }),
                         when: Box::new(crate::when::when_execute("/bin/true")),
+                        ..Default::default()
+                    },
+                    crate::ferro::Task {
+                        description: "run a pipeline".to_owned(),
+                        module: Box::new(crate::modules::shell::Shell {
+                            script: Box::new(crate::lazy::string(
+                                "ls -l / | grep etc > /dev/null".to_owned(),
+                            )),
+                            ..Default::default()
+                        }),
+                        when: Box::new(crate::when::when_execute("/bin/sh -c 'test -d /etc'")),
+                        ..Default::default()
                     },
                     crate::ferro::Task {
                         description: "run cloudformation".to_owned(),
@@ -318,23 +1933,25 @@ mod tests {
                             ..Default::default()
                         }),
                         when: Box::new(crate::when::Always),
+                        ..Default::default()
                     },
                     crate::ferro::Task {
                         description: "run echo".to_owned(),
                         module: Box::new(crate::modules::command::Command {
                             command: Box::new(crate::lazy::string("/bin/echo".to_owned())),
                             args: Box::new(|_| {
-                                vec![Box::new(lazy_format!(
+                                Ok(vec![Box::new(lazy_format!(
                                     "security group is {}",
                                     crate::lazy::state(
                                         "run cloudformation".to_owned(),
                                         "outputs.SecurityGroup".to_owned(),
                                     )
-                                ))]
+                                ))])
                             }),
                             ..Default::default()
                         }),
                         when: Box::new(crate::when::Always),
+                        ..Default::default()
                     },
                 ];
                 let mut vars = HashMap::<String, String>::new();
@@ -342,9 +1959,10 @@ mod tests {
                 let mut playbook = crate::ferro::Playbook {
                     context: crate::ferro::Context {
                         vars: vars,
-                        state: HashMap::<String, serde_json::value::Value>::new(),
+                        ..Default::default()
                     },
                     tasks: tasks,
+                    ..Default::default()
                 };
                 Ok(playbook.run())
             });
@@ -0,0 +1,536 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use serde_yaml::{Mapping, Value};
+
+use crate::inventory::file::{yaml_to_string, yaml_vars};
+
+const MODULES: &[&str] = &[
+    "command",
+    "shell",
+    "copy",
+    "template",
+    "meta",
+    "block",
+    "include_tasks",
+    "import_tasks",
+    "role",
+];
+const KEYWORDS: &[&str] = &[
+    "name",
+    "when",
+    "tags",
+    "notify",
+    "depends_on",
+    "vars",
+    "rescue",
+    "always",
+    "no_log",
+];
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(String),
+    ParseError(String),
+    NotFoundError(String),
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IoError(e) => write!(f, "unable to read tasks: {}", e),
+            Error::ParseError(e) => write!(f, "unable to parse tasks: {}", e),
+            Error::NotFoundError(e) => write!(f, "not found: {}", e),
+        }
+    }
+}
+
+pub struct Loader {
+    pub dir: PathBuf,
+    pub files: Option<PathBuf>,
+    pub templates: Option<PathBuf>,
+    pub roles_path: Vec<PathBuf>,
+    pub handlers: RefCell<Vec<crate::ferro::Task>>,
+    // The canonical paths of the task files being loaded, outermost first,
+    // shared with the loaders of included roles.
+    pub loading: Rc<RefCell<Vec<PathBuf>>>,
+}
+
+impl Loader {
+    pub fn new(dir: &Path) -> Self {
+        Loader {
+            dir: dir.to_owned(),
+            files: None,
+            templates: None,
+            roles_path: vec![PathBuf::from(crate::role::DEFAULT_ROLES_PATH)],
+            handlers: RefCell::new(vec![]),
+            loading: Rc::default(),
+        }
+    }
+
+    pub fn tasks(&self, path: &Path) -> Result<Vec<crate::ferro::Task>, Error> {
+        let full = self.dir.join(path);
+        let canonical = fs::canonicalize(&full)
+            .map_err(|e| Error::IoError(format!("{}: {}", full.display(), e)))?;
+        if let Some(start) = self.loading.borrow().iter().position(|p| p == &canonical) {
+            let cycle: Vec<String> = self.loading.borrow()[start..]
+                .iter()
+                .chain(Some(&canonical))
+                .map(|p| p.display().to_string())
+                .collect();
+            return Err(Error::ParseError(format!(
+                "include cycle {}",
+                cycle.join(" -> ")
+            )));
+        }
+        self.loading.borrow_mut().push(canonical);
+        let tasks = match read(&full) {
+            Ok(Value::Sequence(tasks)) => tasks.iter().map(|t| self.task(t)).collect(),
+            Ok(Value::Null) => Ok(vec![]),
+            Ok(_) => Err(Error::ParseError(format!(
+                "{} must be a list of tasks",
+                path.display()
+            ))),
+            Err(e) => Err(e),
+        };
+        self.loading.borrow_mut().pop();
+        tasks
+    }
+
+    pub fn include_tasks(&self, path: &Path) -> Result<crate::ferro::Task, Error> {
+        Ok(crate::ferro::Task {
+            description: format!("include_tasks {}", path.display()),
+            block: Some(crate::ferro::Block {
+                tasks: self.tasks(path)?,
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+
+    fn task_list(&self, value: Option<&Value>) -> Result<Vec<crate::ferro::Task>, Error> {
+        match value {
+            Some(Value::Sequence(tasks)) => tasks.iter().map(|t| self.task(t)).collect(),
+            None | Some(Value::Null) => Ok(vec![]),
+            Some(_) => Err(Error::ParseError("expected a list of tasks".to_owned())),
+        }
+    }
+
+    fn task(&self, value: &Value) -> Result<crate::ferro::Task, Error> {
+        let task = match value {
+            Value::Mapping(task) => task,
+            _ => return Err(Error::ParseError("task must be a mapping".to_owned())),
+        };
+        let description = get(task, "name").map_or("".to_owned(), yaml_to_string);
+        for (key, _) in task {
+            let key = yaml_to_string(key);
+            if !MODULES.contains(&key.as_str()) && !KEYWORDS.contains(&key.as_str()) {
+                return Err(Error::ParseError(format!(
+                    "unknown key {} in task {}",
+                    key, description
+                )));
+            }
+        }
+        let modules: Vec<&&str> = MODULES.iter().filter(|m| get(task, m).is_some()).collect();
+        if modules.len() != 1 {
+            return Err(Error::ParseError(format!(
+                "task {} must use exactly one of {}",
+                description,
+                MODULES.join(", ")
+            )));
+        }
+
+        let vars = match get(task, "vars") {
+            Some(Value::Mapping(vars)) => yaml_vars(vars),
+            None => HashMap::new(),
+            Some(_) => {
+                return Err(Error::ParseError(format!(
+                    "vars of task {} must be a mapping",
+                    description
+                )))
+            }
+        };
+        let mut result = match (*modules[0], get(task, modules[0]).unwrap()) {
+            ("block", tasks) => crate::ferro::Task {
+                block: Some(crate::ferro::Block {
+                    tasks: self.task_list(Some(tasks))?,
+                    rescue: self.task_list(get(task, "rescue"))?,
+                    always: self.task_list(get(task, "always"))?,
+                    vars: vars.clone(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ("include_tasks", path) | ("import_tasks", path) => {
+                let mut include = self.include_tasks(Path::new(&yaml_to_string(path)))?;
+                if let Some(block) = include.block.as_mut() {
+                    block.vars = vars.clone();
+                }
+                include
+            }
+            ("role", role) => {
+                let (name, mut params) = match role {
+                    Value::Mapping(role) => (
+                        get(role, "role")
+                            .or_else(|| get(role, "name"))
+                            .map_or("".to_owned(), yaml_to_string),
+                        get(role, "vars").map_or(HashMap::new(), |v| match v {
+                            Value::Mapping(v) => yaml_vars(v),
+                            _ => HashMap::new(),
+                        }),
+                    ),
+                    role => (yaml_to_string(role), HashMap::new()),
+                };
+                params.extend(vars.clone());
+                let mut role = crate::role::Role::find(&name, &self.roles_path)?;
+                role.params = params;
+                let (task, handlers) = role.load_within(&self.loading)?;
+                self.handlers.borrow_mut().extend(handlers);
+                task
+            }
+            (module, value) => {
+                if !vars.is_empty() {
+                    return Err(Error::ParseError(format!(
+                        "vars are only supported on blocks, includes and roles, not task {}",
+                        description
+                    )));
+                }
+                crate::ferro::Task {
+                    module: self.module(module, value)?,
+                    ..Default::default()
+                }
+            }
+        };
+
+        result.no_log = match get(task, "no_log").map(boolean) {
+            None => false,
+            Some(Some(no_log)) => no_log,
+            Some(None) => {
+                return Err(Error::ParseError(format!(
+                    "no_log of task {} must be true or false",
+                    description
+                )))
+            }
+        };
+        if description != "" {
+            result.description = description;
+        }
+        if let Some(when) = get(task, "when") {
+            result.when = Box::new(crate::when::when_execute(&yaml_to_string(when)));
+        }
+        result.tags = strings(get(task, "tags"));
+        result.notify = strings(get(task, "notify"));
+        result.depends_on = strings(get(task, "depends_on"));
+        Ok(result)
+    }
+
+    fn module(&self, module: &str, value: &Value) -> Result<Box<dyn crate::ferro::Module>, Error> {
+        let field = |name: &str| match value {
+            Value::Mapping(args) => get(args, name).map(yaml_to_string),
+            _ => None,
+        };
+        let required = |name: &str| {
+            field(name).ok_or_else(|| Error::ParseError(format!("{} requires {}", module, name)))
+        };
+        match module {
+            "command" => {
+                let line = match value {
+                    Value::Mapping(_) => required("cmd")?,
+                    value => yaml_to_string(value),
+                };
+                // Words are split before rendering so that a rendered value
+                // containing spaces or quotes stays a single argument.
+                let mut words = split_words(&line)
+                    .map_err(|e| Error::ParseError(format!("{}: {}", line, e)))?
+                    .into_iter();
+                let command = words
+                    .next()
+                    .ok_or_else(|| Error::ParseError(format!("{} requires cmd", module)))?;
+                let args: Vec<String> = words.collect();
+                Ok(Box::new(crate::modules::command::Command {
+                    command: Box::new(crate::lazy::template(command)),
+                    args: Box::new(move |_| {
+                        Ok(args
+                            .iter()
+                            .map(|a| {
+                                Box::new(crate::lazy::template(a.clone()))
+                                    as Box<crate::lazy::String>
+                            })
+                            .collect())
+                    }),
+                    creates: Box::new(crate::lazy::template(field("creates").unwrap_or_default())),
+                    removes: Box::new(crate::lazy::template(field("removes").unwrap_or_default())),
+                }))
+            }
+            "shell" => {
+                let script = match value {
+                    Value::Mapping(_) => required("cmd")?,
+                    value => yaml_to_string(value),
+                };
+                let mut shell = crate::modules::shell::Shell {
+                    script: Box::new(crate::lazy::template(script)),
+                    ..Default::default()
+                };
+                if let Some(executable) = field("executable") {
+                    shell.executable = Box::new(crate::lazy::template(executable));
+                }
+                Ok(Box::new(shell))
+            }
+            "copy" => Ok(Box::new(crate::modules::copy::Copy {
+                src: Box::new(crate::lazy::template(resolve(
+                    &self.files,
+                    required("src")?,
+                ))),
+                dest: Box::new(crate::lazy::template(required("dest")?)),
+            })),
+            "template" => Ok(Box::new(crate::modules::template::Template {
+                src: Box::new(crate::lazy::template(resolve(
+                    &self.templates,
+                    required("src")?,
+                ))),
+                dest: Box::new(crate::lazy::template(required("dest")?)),
+            })),
+            "meta" => match yaml_to_string(value).as_str() {
+                crate::modules::meta::FLUSH_HANDLERS => {
+                    Ok(Box::new(crate::modules::meta::FlushHandlers))
+                }
+                action => Err(Error::ParseError(format!("unknown meta action {}", action))),
+            },
+            module => Err(Error::ParseError(format!("unknown module {}", module))),
+        }
+    }
+}
+
+pub fn tasks(path: &Path) -> Result<Vec<crate::ferro::Task>, Error> {
+    Loader::new(path.parent().unwrap_or_else(|| Path::new("")))
+        .tasks(Path::new(path.file_name().unwrap_or_default()))
+}
+
+pub fn include_tasks(path: &Path) -> Result<crate::ferro::Task, Error> {
+    Loader::new(path.parent().unwrap_or_else(|| Path::new("")))
+        .include_tasks(Path::new(path.file_name().unwrap_or_default()))
+}
+
+pub fn playbook(path: &Path, roles_path: &[PathBuf]) -> Result<crate::ferro::Playbook, Error> {
+    let play = match read(path)? {
+        Value::Mapping(play) => play,
+        Value::Sequence(mut plays) if plays.len() == 1 => match plays.remove(0) {
+            Value::Mapping(play) => play,
+            _ => return Err(Error::ParseError("play must be a mapping".to_owned())),
+        },
+        _ => {
+            return Err(Error::ParseError(format!(
+                "{} must contain a single play",
+                path.display()
+            )))
+        }
+    };
+    let mut loader = Loader::new(path.parent().unwrap_or_else(|| Path::new("")));
+    loader.roles_path = roles_path.to_owned();
+
+    let mut playbook = crate::ferro::Playbook {
+        roles_path: roles_path.to_owned(),
+        ..Default::default()
+    };
+    if let Some(hosts) = get(&play, "hosts") {
+        playbook.hosts = yaml_to_string(hosts);
+    }
+    if let Some(Value::Mapping(vars)) = get(&play, "vars") {
+        playbook.context.vars = yaml_vars(vars);
+    }
+    if let Some(Value::Sequence(roles)) = get(&play, "roles") {
+        for role in roles {
+            let mut task = Mapping::new();
+            task.insert(Value::String("role".to_owned()), role.clone());
+            playbook.tasks.push(loader.task(&Value::Mapping(task))?);
+        }
+    }
+    playbook
+        .tasks
+        .extend(loader.task_list(get(&play, "tasks"))?);
+    playbook.handlers = loader.task_list(get(&play, "handlers"))?;
+    playbook.handlers.extend(loader.handlers.into_inner());
+    Ok(playbook)
+}
+
+// Splits a command line into words, keeping each `{{ ... }}` expression
+// whole inside the word it appears in.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let marker = |index: usize| format!("\u{1}{}\u{1}", index);
+    let mut expressions = vec![];
+    let mut protected = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .map(|end| start + end + 2)
+            .ok_or_else(|| "unclosed {{".to_owned())?;
+        protected.push_str(&rest[..start]);
+        protected.push_str(&marker(expressions.len()));
+        expressions.push(&rest[start..end]);
+        rest = &rest[end..];
+    }
+    protected.push_str(rest);
+    let words = shell_words::split(&protected).map_err(|e| e.to_string())?;
+    Ok(words
+        .into_iter()
+        .map(|word| {
+            expressions
+                .iter()
+                .enumerate()
+                .fold(word, |word, (i, e)| word.replace(&marker(i), e))
+        })
+        .collect())
+}
+
+fn read(path: &Path) -> Result<Value, Error> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| Error::IoError(format!("{}: {}", path.display(), e)))?;
+    serde_yaml::from_str(&contents)
+        .map_err(|e| Error::ParseError(format!("{}: {}", path.display(), e)))
+}
+
+// Also accepts the YAML 1.1 spellings, such as yes and True, that the parser
+// leaves as strings.
+fn boolean(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::String(s) => match s.as_str() {
+            "true" | "True" | "TRUE" | "yes" | "Yes" | "YES" | "on" | "On" | "ON" => Some(true),
+            "false" | "False" | "FALSE" | "no" | "No" | "NO" | "off" | "Off" | "OFF" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn get<'a>(mapping: &'a Mapping, key: &str) -> Option<&'a Value> {
+    mapping.get(&Value::String(key.to_owned()))
+}
+
+fn strings(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::Sequence(values)) => values.iter().map(yaml_to_string).collect(),
+        Some(Value::Null) | None => vec![],
+        Some(value) => vec![yaml_to_string(value)],
+    }
+}
+
+fn resolve(dir: &Option<PathBuf>, path: String) -> String {
+    match dir {
+        Some(dir) if Path::new(&path).is_relative() => dir.join(path).display().to_string(),
+        _ => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words("/bin/echo {{ a }} 'x {{b}}' y{{ c }}").unwrap(),
+            vec!["/bin/echo", "{{ a }}", "x {{b}}", "y{{ c }}"]
+        );
+        assert!(split_words("echo {{ a").is_err());
+        assert!(split_words("echo 'a").is_err());
+    }
+
+    #[test]
+    fn test_playbook() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(
+            root.join("site.yml"),
+            "hosts: web\nvars:\n  name: world\ntasks:\n  - name: hello\n    \
+             shell: echo hello {{ name }}\n    tags: [greet]\n    notify: done\n  \
+             - meta: flush_handlers\nhandlers:\n  - name: done\n    command: /bin/true\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("bad.yml"),
+            "- name: both\n  shell: a\n  command: b\n",
+        )
+        .unwrap();
+        fs::write(root.join("unknown.yml"), "- name: typo\n  shel: a\n").unwrap();
+
+        let imported = playbook(&root.join("site.yml"), &[]).unwrap();
+        assert_eq!(imported.hosts, "web");
+        assert_eq!(imported.tasks.len(), 2);
+        assert_eq!(imported.tasks[0].tags, vec!["greet"]);
+        assert_eq!(imported.tasks[0].notify, vec!["done"]);
+        assert_eq!(imported.handlers.len(), 1);
+
+        let mut playbook = crate::ferro::Playbook::default();
+        playbook.import_playbook(imported);
+        let result = playbook.run();
+        assert!(result.succeeded());
+        let value = &playbook.context.state["hello"];
+        assert_eq!(
+            crate::ferro::find("stdout", value).unwrap(),
+            "hello world\n"
+        );
+
+        fs::write(
+            root.join("words.yml"),
+            "- name: words\n  command: /bin/echo {{ spaced }}\n\
+             - name: undefined\n  command: /bin/echo {{ missing }}\n",
+        )
+        .unwrap();
+        let mut playbook = crate::ferro::Playbook {
+            tasks: tasks(&root.join("words.yml")).unwrap(),
+            ..Default::default()
+        };
+        playbook
+            .context
+            .vars
+            .insert("spaced".to_owned(), "a  'b'".to_owned());
+        let result = playbook.run();
+        let value = &playbook.context.state["words"];
+        assert_eq!(crate::ferro::find("stdout", value).unwrap(), "a  'b'\n");
+        assert_eq!(
+            result.results[1].error,
+            Some("undefined variable missing".to_owned())
+        );
+
+        assert!(tasks(&root.join("bad.yml")).is_err());
+        assert!(tasks(&root.join("unknown.yml")).is_err());
+        assert!(tasks(&root.join("missing.yml")).is_err());
+
+        fs::write(root.join("a.yml"), "- include_tasks: b.yml\n").unwrap();
+        fs::write(root.join("b.yml"), "- import_tasks: a.yml\n").unwrap();
+        let error = tasks(&root.join("a.yml")).err().unwrap().to_string();
+        assert!(error.contains("include cycle"), "{}", error);
+        assert!(error.ends_with("a.yml"), "{}", error);
+        fs::write(
+            root.join("twice.yml"),
+            "- include_tasks: words.yml\n- include_tasks: words.yml\n",
+        )
+        .unwrap();
+        assert_eq!(tasks(&root.join("twice.yml")).unwrap().len(), 2);
+
+        fs::write(
+            root.join("no_log.yml"),
+            "- name: quiet\n  shell: a\n  no_log: True\n- name: also quiet\n  shell: b\n  \
+             no_log: yes\n- name: loud\n  shell: c\n  no_log: false\n",
+        )
+        .unwrap();
+        let loaded = tasks(&root.join("no_log.yml")).unwrap();
+        assert!(loaded[0].no_log && loaded[1].no_log);
+        assert!(!loaded[2].no_log);
+        fs::write(root.join("maybe.yml"), "- shell: a\n  no_log: maybe\n").unwrap();
+        assert!(tasks(&root.join("maybe.yml")).is_err());
+    }
+}
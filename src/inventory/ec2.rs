@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::default::Default;
+use std::vec::Vec;
+
+use rusoto_ec2::{DescribeInstancesRequest, Ec2 as EC2, Ec2Client, Filter, Instance};
+
+use super::{Error, Inventory};
+
+const EC2_GROUP: &str = "ec2";
+const RUNNING: &str = "running";
+
+pub struct Ec2 {
+    pub tags: HashMap<String, String>,
+    pub group_by_tags: Vec<String>,
+    pub use_public_ip: bool,
+    pub ec2: Ec2Client,
+}
+
+impl Default for Ec2 {
+    fn default() -> Self {
+        Ec2 {
+            tags: HashMap::new(),
+            group_by_tags: vec![],
+            use_public_ip: false,
+            ec2: Ec2Client::new(crate::lookup::aws::region()),
+        }
+    }
+}
+
+impl Ec2 {
+    fn filters(&self) -> Vec<Filter> {
+        let mut filters = vec![Filter {
+            name: Some("instance-state-name".to_owned()),
+            values: Some(vec![RUNNING.to_owned()]),
+        }];
+        for (key, value) in &self.tags {
+            filters.push(Filter {
+                name: Some(format!("tag:{}", key)),
+                values: Some(vec![value.to_owned()]),
+            });
+        }
+        filters
+    }
+
+    fn instances(&self) -> Result<Vec<Instance>, Error> {
+        let mut instances = vec![];
+        let mut next_token = None;
+        loop {
+            let result = self
+                .ec2
+                .describe_instances(DescribeInstancesRequest {
+                    filters: Some(self.filters()),
+                    next_token: next_token,
+                    ..Default::default()
+                })
+                .sync()
+                .map_err(|e| Error::SourceError(e.to_string()))?;
+            for reservation in result.reservations.unwrap_or_default() {
+                instances.extend(reservation.instances.unwrap_or_default());
+            }
+            next_token = result.next_token;
+            if next_token.is_none() {
+                return Ok(instances);
+            }
+        }
+    }
+}
+
+impl super::Source for Ec2 {
+    fn load(&self) -> Result<Inventory, Error> {
+        let mut inventory = Inventory::default();
+        for instance in self.instances()? {
+            let instance_id = match &instance.instance_id {
+                Some(instance_id) => instance_id.to_owned(),
+                None => continue,
+            };
+            let tags: HashMap<String, String> = instance
+                .tags
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|t| match (t.key, t.value) {
+                    (Some(k), Some(v)) => Some((k, v)),
+                    _ => None,
+                })
+                .collect();
+
+            let mut vars = HashMap::new();
+            let address = if self.use_public_ip {
+                instance.public_ip_address.clone()
+            } else {
+                instance.private_ip_address.clone()
+            };
+            if let Some(address) = address {
+                vars.insert(crate::connection::SSH_HOST.to_owned(), address);
+            }
+            vars.insert("ec2_instance_id".to_owned(), instance_id.clone());
+            if let Some(instance_type) = instance.instance_type {
+                vars.insert("ec2_instance_type".to_owned(), instance_type);
+            }
+            if let Some(private_ip) = instance.private_ip_address {
+                vars.insert("ec2_private_ip_address".to_owned(), private_ip);
+            }
+            if let Some(public_ip) = instance.public_ip_address {
+                vars.insert("ec2_public_ip_address".to_owned(), public_ip);
+            }
+            for (key, value) in &tags {
+                vars.insert(format!("ec2_tag_{}", key), value.to_owned());
+            }
+
+            inventory.add_group_host(EC2_GROUP, &instance_id);
+            inventory.add_host(&instance_id, vars);
+            for key in &self.group_by_tags {
+                if let Some(value) = tags.get(key) {
+                    inventory.add_group_host(&format!("tag_{}_{}", key, value), &instance_id);
+                }
+            }
+        }
+        Ok(inventory)
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use serde_yaml::Value;
+
+use super::{Error, Inventory, ALL, UNGROUPED};
+
+pub struct File {
+    pub path: PathBuf,
+}
+
+impl super::Source for File {
+    fn load(&self) -> Result<Inventory, Error> {
+        let contents = fs::read_to_string(&self.path)
+            .map_err(|e| Error::IoError(format!("{}: {}", self.path.display(), e)))?;
+        match self.path.extension().and_then(|e| e.to_str()) {
+            Some("yml") | Some("yaml") | Some("json") => parse_yaml(&contents),
+            _ => parse_ini(&contents),
+        }
+    }
+}
+
+pub fn parse_yaml(contents: &str) -> Result<Inventory, Error> {
+    let value: Value =
+        serde_yaml::from_str(contents).map_err(|e| Error::ParseError(e.to_string()))?;
+    let mut inventory = Inventory::default();
+    match value {
+        Value::Mapping(groups) => {
+            for (name, group) in groups {
+                let name = yaml_to_string(&name);
+                add_yaml_group(&mut inventory, &name, &group)?;
+            }
+            Ok(inventory)
+        }
+        Value::Null => Ok(inventory),
+        _ => Err(Error::ParseError(
+            "inventory must be a mapping of groups".to_owned(),
+        )),
+    }
+}
+
+fn add_yaml_group(inventory: &mut Inventory, name: &str, group: &Value) -> Result<(), Error> {
+    inventory.add_group(name);
+    let group = match group {
+        Value::Mapping(group) => group,
+        Value::Null => return Ok(()),
+        _ => {
+            return Err(Error::ParseError(format!(
+                "group {} must be a mapping",
+                name
+            )))
+        }
+    };
+
+    if let Some(Value::Mapping(vars)) = group.get(&Value::String("vars".to_owned())) {
+        let vars = yaml_vars(vars);
+        inventory.add_group(name).vars.extend(vars);
+    }
+    if let Some(Value::Mapping(hosts)) = group.get(&Value::String("hosts".to_owned())) {
+        for (host, vars) in hosts {
+            let host = yaml_to_string(host);
+            inventory.add_group_host(name, &host);
+            if let Value::Mapping(vars) = vars {
+                inventory.add_host(&host, yaml_vars(vars));
+            }
+        }
+    }
+    if let Some(Value::Mapping(children)) = group.get(&Value::String("children".to_owned())) {
+        for (child, child_group) in children {
+            let child = yaml_to_string(child);
+            inventory.add_group_child(name, &child);
+            add_yaml_group(inventory, &child, child_group)?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn yaml_vars(vars: &serde_yaml::Mapping) -> HashMap<String, String> {
+    vars.iter()
+        .map(|(k, v)| (yaml_to_string(k), yaml_to_string(v)))
+        .collect()
+}
+
+pub(crate) fn yaml_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.to_owned(),
+        Value::Null => "".to_owned(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        v => serde_json::to_value(v)
+            .map(|v| v.to_string())
+            .unwrap_or_default(),
+    }
+}
+
+pub fn parse_ini(contents: &str) -> Result<Inventory, Error> {
+    let mut inventory = Inventory::default();
+    let mut section = UNGROUPED.to_owned();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line == "" || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err(Error::ParseError(format!(
+                    "invalid section header on line {}",
+                    number + 1
+                )));
+            }
+            section = line[1..line.len() - 1].trim().to_owned();
+            let group = section.split(':').next().unwrap_or(ALL).to_owned();
+            inventory.add_group(&group);
+            continue;
+        }
+
+        let mut parts = section.splitn(2, ':');
+        let group = parts.next().unwrap_or(ALL);
+        match parts.next() {
+            Some("vars") => {
+                let (key, value) = parse_assignment(line).ok_or_else(|| {
+                    Error::ParseError(format!("expected key=value on line {}", number + 1))
+                })?;
+                inventory.add_group(group).vars.insert(key, value);
+            }
+            Some("children") => inventory.add_group_child(group, line),
+            Some(kind) => {
+                return Err(Error::ParseError(format!(
+                    "unknown section type {} on line {}",
+                    kind,
+                    number + 1
+                )))
+            }
+            None => {
+                let words = shell_words::split(line)
+                    .map_err(|e| Error::ParseError(format!("{} on line {}", e, number + 1)))?;
+                let mut words = words.into_iter();
+                let host = words.next().unwrap_or_default();
+                let mut vars = HashMap::new();
+                for word in words {
+                    let (key, value) = parse_assignment(&word).ok_or_else(|| {
+                        Error::ParseError(format!("expected key=value on line {}", number + 1))
+                    })?;
+                    vars.insert(key, value);
+                }
+                inventory.add_group_host(group, &host);
+                inventory.add_host(&host, vars);
+            }
+        }
+    }
+    Ok(inventory)
+}
+
+fn parse_assignment(s: &str) -> Option<(String, String)> {
+    let mut parts = s.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(key), Some(value)) => Some((
+            key.trim().to_owned(),
+            value.trim().trim_matches('"').to_owned(),
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_yaml() {
+        let inventory = parse_yaml(
+            r#"
+all:
+  vars:
+    ssh_user: admin
+  children:
+    web:
+      hosts:
+        web1:
+          ssh_host: 10.0.0.1
+        web2:
+      vars:
+        port: 8080
+"#,
+        )
+        .unwrap();
+        assert_eq!(inventory.hosts.len(), 2);
+        let vars = inventory.host_vars("web1");
+        assert_eq!(vars.get("ssh_user").unwrap(), "admin");
+        assert_eq!(vars.get("ssh_host").unwrap(), "10.0.0.1");
+        assert_eq!(vars.get("port").unwrap(), "8080");
+    }
+
+    #[test]
+    fn test_parse_ini() {
+        let inventory = parse_ini(
+            r#"
+bastion ssh_port=2222
+
+[web]
+web1 ssh_host=10.0.0.1
+web2
+
+[web:vars]
+port=8080
+
+[prod:children]
+web
+"#,
+        )
+        .unwrap();
+        assert_eq!(inventory.hosts.len(), 3);
+        assert_eq!(inventory.group_hosts(UNGROUPED).len(), 1);
+        assert_eq!(inventory.select("prod").len(), 2);
+        let vars = inventory.host_vars("web1");
+        assert_eq!(vars.get("ssh_host").unwrap(), "10.0.0.1");
+        assert_eq!(vars.get("port").unwrap(), "8080");
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::vec::Vec;
+
+pub mod ec2;
+pub mod file;
+pub mod script;
+
+pub const ALL: &str = "all";
+pub const UNGROUPED: &str = "ungrouped";
+pub const INVENTORY_HOSTNAME: &str = "inventory_hostname";
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(String),
+    ParseError(String),
+    SourceError(String),
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IoError(e) => write!(f, "unable to read inventory: {}", e),
+            Error::ParseError(e) => write!(f, "unable to parse inventory: {}", e),
+            Error::SourceError(e) => write!(f, "inventory source failed: {}", e),
+        }
+    }
+}
+
+pub trait Source {
+    fn load(&self) -> Result<Inventory, Error>;
+}
+
+#[derive(Debug, Default)]
+pub struct Host {
+    pub name: String,
+    pub vars: HashMap<String, String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Group {
+    pub name: String,
+    pub hosts: Vec<String>,
+    pub children: Vec<String>,
+    pub vars: HashMap<String, String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Inventory {
+    pub hosts: Vec<Host>,
+    pub groups: Vec<Group>,
+}
+
+impl Inventory {
+    pub fn load(sources: &[Box<dyn Source>]) -> Result<Inventory, Error> {
+        let mut inventory = Inventory::default();
+        for source in sources {
+            inventory.merge(source.load()?);
+        }
+        Ok(inventory)
+    }
+
+    pub fn merge(&mut self, other: Inventory) {
+        for host in other.hosts {
+            self.add_host(&host.name, host.vars);
+        }
+        for group in other.groups {
+            let existing = self.add_group(&group.name);
+            for host in group.hosts {
+                if !existing.hosts.contains(&host) {
+                    existing.hosts.push(host);
+                }
+            }
+            for child in group.children {
+                if !existing.children.contains(&child) {
+                    existing.children.push(child);
+                }
+            }
+            existing.vars.extend(group.vars);
+        }
+    }
+
+    pub fn add_host(&mut self, name: &str, vars: HashMap<String, String>) {
+        match self.hosts.iter_mut().find(|h| h.name == name) {
+            Some(host) => host.vars.extend(vars),
+            None => self.hosts.push(Host {
+                name: name.to_owned(),
+                vars: vars,
+            }),
+        }
+    }
+
+    pub fn add_group(&mut self, name: &str) -> &mut Group {
+        let index = match self.groups.iter().position(|g| g.name == name) {
+            Some(index) => index,
+            None => {
+                self.groups.push(Group {
+                    name: name.to_owned(),
+                    ..Default::default()
+                });
+                self.groups.len() - 1
+            }
+        };
+        &mut self.groups[index]
+    }
+
+    pub fn add_group_host(&mut self, group: &str, host: &str) {
+        if self.host(host).is_none() {
+            self.add_host(host, HashMap::new());
+        }
+        let group = self.add_group(group);
+        if !group.hosts.iter().any(|h| h == host) {
+            group.hosts.push(host.to_owned());
+        }
+    }
+
+    pub fn add_group_child(&mut self, group: &str, child: &str) {
+        self.add_group(child);
+        let group = self.add_group(group);
+        if !group.children.iter().any(|c| c == child) {
+            group.children.push(child.to_owned());
+        }
+    }
+
+    pub fn host(&self, name: &str) -> Option<&Host> {
+        self.hosts.iter().find(|h| h.name == name)
+    }
+
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    pub fn group_hosts(&self, name: &str) -> Vec<&Host> {
+        if name == ALL {
+            return self.hosts.iter().collect();
+        }
+        let mut names = vec![];
+        self.collect_group_hosts(name, &mut vec![], &mut names);
+        self.hosts
+            .iter()
+            .filter(|h| names.contains(&h.name.as_str()))
+            .collect()
+    }
+
+    fn collect_group_hosts<'a>(
+        &'a self,
+        name: &'a str,
+        visited: &mut Vec<&'a str>,
+        names: &mut Vec<&'a str>,
+    ) {
+        if visited.contains(&name) {
+            return;
+        }
+        visited.push(name);
+        if let Some(group) = self.group(name) {
+            names.extend(group.hosts.iter().map(|h| h.as_str()));
+            for child in &group.children {
+                self.collect_group_hosts(child, visited, names);
+            }
+        }
+    }
+
+    fn group_depth(&self, name: &str, visited: &mut Vec<String>) -> usize {
+        if name == ALL || visited.iter().any(|v| v == name) {
+            return 0;
+        }
+        visited.push(name.to_owned());
+        self.groups
+            .iter()
+            .filter(|g| g.name != ALL && g.children.iter().any(|c| c == name))
+            .map(|g| self.group_depth(&g.name, visited) + 1)
+            .max()
+            .unwrap_or(1)
+    }
+
+    pub fn host_groups(&self, name: &str) -> Vec<&Group> {
+        let mut groups: Vec<(usize, &Group)> = self
+            .groups
+            .iter()
+            .filter(|g| g.name == ALL || self.group_hosts(&g.name).iter().any(|h| h.name == name))
+            .map(|g| (self.group_depth(&g.name, &mut vec![]), g))
+            .collect();
+        groups.sort_by(|(a_depth, a), (b_depth, b)| {
+            a_depth.cmp(b_depth).then_with(|| a.name.cmp(&b.name))
+        });
+        groups.into_iter().map(|(_, g)| g).collect()
+    }
+
+    pub fn host_vars(&self, name: &str) -> HashMap<String, String> {
+        let mut vars = HashMap::new();
+        for group in self.host_groups(name) {
+            vars.extend(group.vars.clone());
+        }
+        if let Some(host) = self.host(name) {
+            vars.extend(host.vars.clone());
+        }
+        vars.insert(INVENTORY_HOSTNAME.to_owned(), name.to_owned());
+        vars
+    }
+
+    pub fn select(&self, pattern: &str) -> Vec<&Host> {
+        let mut selected: Vec<&str> = vec![];
+        for term in terms(pattern).iter() {
+            if let Some(term) = term.strip_prefix('!') {
+                let excluded = self.resolve(term);
+                selected.retain(|h| !excluded.contains(h));
+            } else if let Some(term) = term.strip_prefix('&') {
+                let intersected = self.resolve(term);
+                selected.retain(|h| intersected.contains(h));
+            } else {
+                for host in self.resolve(term) {
+                    if !selected.contains(&host) {
+                        selected.push(host);
+                    }
+                }
+            }
+        }
+        self.hosts
+            .iter()
+            .filter(|h| selected.contains(&h.name.as_str()))
+            .collect()
+    }
+
+    fn resolve(&self, term: &str) -> Vec<&str> {
+        if term == ALL || term == "*" {
+            return self.hosts.iter().map(|h| h.name.as_str()).collect();
+        }
+        let mut names = vec![];
+        for group in self.groups.iter().filter(|g| matches(term, &g.name)) {
+            names.extend(
+                self.group_hosts(&group.name)
+                    .iter()
+                    .map(|h| h.name.as_str()),
+            );
+        }
+        for host in self.hosts.iter().filter(|h| matches(term, &h.name)) {
+            names.push(host.name.as_str());
+        }
+        names
+    }
+}
+
+// Splits a host pattern on `,` and `:`. IPv6 addresses can be written in
+// brackets (`[::1]:web`), or bare when separated by commas only (`::1,web1`).
+fn terms(pattern: &str) -> Vec<String> {
+    let mut terms = vec![];
+    for part in pattern.split(',') {
+        if part
+            .trim()
+            .trim_start_matches(&['!', '&'][..])
+            .parse::<Ipv6Addr>()
+            .is_ok()
+        {
+            terms.push(part.trim().to_owned());
+            continue;
+        }
+        let mut term = "".to_owned();
+        let mut bracketed = false;
+        for c in part.chars() {
+            match c {
+                '[' => bracketed = true,
+                ']' => bracketed = false,
+                ':' if !bracketed => terms.push(std::mem::take(&mut term)),
+                c => term.push(c),
+            }
+        }
+        terms.push(term);
+    }
+    terms
+        .into_iter()
+        .map(|t| t.trim().to_owned())
+        .filter(|t| t != "")
+        .collect()
+}
+
+pub fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.find('*') {
+        None => pattern == name,
+        Some(index) => {
+            let (prefix, rest) = (&pattern[..index], &pattern[index + 1..]);
+            if !name.starts_with(prefix) {
+                return false;
+            }
+            let name = &name[prefix.len()..];
+            (0..=name.len())
+                .filter(|i| name.is_char_boundary(*i))
+                .any(|i| matches(rest, &name[i..]))
+        }
+    }
+}
+
+pub fn value_to_string(value: &serde_json::value::Value) -> String {
+    match value {
+        serde_json::value::Value::String(s) => s.to_owned(),
+        serde_json::value::Value::Null => "".to_owned(),
+        v => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inventory() -> Inventory {
+        let mut inventory = Inventory::default();
+        inventory.add_group_host("web", "web1");
+        inventory.add_group_host("web", "web2");
+        inventory.add_group_host("db", "db1");
+        inventory.add_group_child("prod", "web");
+        inventory.add_group_child("prod", "db");
+        inventory
+            .add_group(ALL)
+            .vars
+            .insert("region".to_owned(), "us-east-1".to_owned());
+        inventory
+            .add_group("prod")
+            .vars
+            .insert("env".to_owned(), "prod".to_owned());
+        inventory
+            .add_group("web")
+            .vars
+            .insert("env".to_owned(), "web".to_owned());
+        inventory.add_host(
+            "web2",
+            vec![("env".to_owned(), "canary".to_owned())]
+                .into_iter()
+                .collect(),
+        );
+        inventory
+    }
+
+    fn names(hosts: Vec<&Host>) -> Vec<&str> {
+        hosts.iter().map(|h| h.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_select() {
+        let inventory = inventory();
+        assert_eq!(names(inventory.select("all")), vec!["web1", "web2", "db1"]);
+        assert_eq!(names(inventory.select("prod:!db")), vec!["web1", "web2"]);
+        assert_eq!(names(inventory.select("web*:&prod")), vec!["web1", "web2"]);
+        assert_eq!(names(inventory.select("db1,web1")), vec!["web1", "db1"]);
+        assert!(inventory.select("missing").is_empty());
+    }
+
+    #[test]
+    fn test_select_ipv6() {
+        let mut inventory = inventory();
+        inventory.add_group_host("v6", "2001:db8::1");
+        inventory.add_group_host("v6", "::1");
+        assert_eq!(names(inventory.select("::1,web1")), vec!["web1", "::1"]);
+        assert_eq!(names(inventory.select("v6:![2001:db8::1]")), vec!["::1"]);
+        assert_eq!(
+            terms("[::1]:&web,!fe80::1"),
+            vec!["::1", "&web", "!fe80::1"]
+        );
+    }
+
+    #[test]
+    fn test_host_vars() {
+        let inventory = inventory();
+        let vars = inventory.host_vars("web1");
+        assert_eq!(vars.get("region").unwrap(), "us-east-1");
+        assert_eq!(vars.get("env").unwrap(), "web");
+        assert_eq!(vars.get(INVENTORY_HOSTNAME).unwrap(), "web1");
+
+        assert_eq!(inventory.host_vars("web2").get("env").unwrap(), "canary");
+        assert_eq!(inventory.host_vars("db1").get("env").unwrap(), "prod");
+    }
+}
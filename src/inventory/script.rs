@@ -0,0 +1,114 @@
+use std::process;
+use std::vec::Vec;
+
+use serde_json::value::Value;
+
+use super::{value_to_string, Error, Inventory};
+
+const META: &str = "_meta";
+const HOSTVARS: &str = "hostvars";
+
+pub struct Script {
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+impl super::Source for Script {
+    fn load(&self) -> Result<Inventory, Error> {
+        let output = process::Command::new(&self.command)
+            .args(&self.args)
+            .stdin(process::Stdio::null())
+            .output()
+            .map_err(|e| Error::SourceError(format!("{}: {}", self.command, e)))?;
+        if !output.status.success() {
+            return Err(Error::SourceError(format!(
+                "{}: {}",
+                self.command,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        let value: Value =
+            serde_json::from_slice(&output.stdout).map_err(|e| Error::ParseError(e.to_string()))?;
+        parse_json(&value)
+    }
+}
+
+pub fn parse_json(value: &Value) -> Result<Inventory, Error> {
+    let groups = match value {
+        Value::Object(groups) => groups,
+        _ => {
+            return Err(Error::ParseError(
+                "inventory must be an object of groups".to_owned(),
+            ))
+        }
+    };
+
+    let mut inventory = Inventory::default();
+    for (name, group) in groups.iter().filter(|(name, _)| *name != META) {
+        inventory.add_group(name);
+        match group {
+            Value::Array(hosts) => {
+                for host in hosts {
+                    inventory.add_group_host(name, &value_to_string(host));
+                }
+            }
+            Value::Object(group) => {
+                if let Some(Value::Array(hosts)) = group.get("hosts") {
+                    for host in hosts {
+                        inventory.add_group_host(name, &value_to_string(host));
+                    }
+                }
+                if let Some(Value::Array(children)) = group.get("children") {
+                    for child in children {
+                        inventory.add_group_child(name, &value_to_string(child));
+                    }
+                }
+                if let Some(Value::Object(vars)) = group.get("vars") {
+                    let vars = vars
+                        .iter()
+                        .map(|(k, v)| (k.to_owned(), value_to_string(v)))
+                        .collect::<Vec<(String, String)>>();
+                    inventory.add_group(name).vars.extend(vars);
+                }
+            }
+            _ => return Err(Error::ParseError(format!("invalid group {}", name))),
+        }
+    }
+
+    if let Some(Value::Object(hostvars)) = groups.get(META).and_then(|m| m.get(HOSTVARS)) {
+        for (host, vars) in hostvars {
+            if let Value::Object(vars) = vars {
+                let vars = vars
+                    .iter()
+                    .map(|(k, v)| (k.to_owned(), value_to_string(v)))
+                    .collect();
+                inventory.add_host(host, vars);
+            }
+        }
+    }
+    Ok(inventory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inventory::Source;
+
+    #[test]
+    fn test_script() {
+        let script = Script {
+            command: "/bin/echo".to_owned(),
+            args: vec![r#"{
+                "web": {"hosts": ["web1"], "vars": {"port": 8080}},
+                "prod": {"children": ["web"]},
+                "_meta": {"hostvars": {"web1": {"ssh_host": "10.0.0.1"}}}
+            }"#
+            .to_owned()],
+        };
+        let inventory = script.load().unwrap();
+        assert_eq!(inventory.select("prod").len(), 1);
+        let vars = inventory.host_vars("web1");
+        assert_eq!(vars.get("port").unwrap(), "8080");
+        assert_eq!(vars.get("ssh_host").unwrap(), "10.0.0.1");
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::Value;
+
+pub const DEFAULT_PATH: &str = ".ferro-journal.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Entry {
+    pub completed: Vec<String>,
+    pub failed: Option<String>,
+    pub state: HashMap<String, Value>,
+    // Tasks whose outputs were redacted or left out of state, so they are run
+    // again rather than restored when the playbook is resumed or started at a
+    // later task.
+    #[serde(default)]
+    pub redacted: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Journal {
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    pub hosts: HashMap<String, Entry>,
+}
+
+impl Journal {
+    pub fn load(path: &Path) -> Result<Journal, io::Error> {
+        let mut journal: Journal = match fs::read(path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Journal::default(),
+            Err(e) => return Err(e),
+        };
+        journal.path = Some(path.to_owned());
+        Ok(journal)
+    }
+
+    pub fn entry(&self, host: &Option<String>) -> Option<&Entry> {
+        self.hosts.get(crate::state::host_key(host))
+    }
+
+    // Only a resumed run keeps the tasks completed by the previous one.
+    pub fn begin(&mut self, host: &Option<String>, resuming: bool) {
+        let entry = self
+            .hosts
+            .entry(crate::state::host_key(host).to_owned())
+            .or_default();
+        entry.failed = None;
+        if !resuming {
+            entry.completed.clear();
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        result: &crate::ferro::TaskResult,
+        state: &HashMap<String, Value>,
+        redacted: bool,
+    ) -> Result<(), io::Error> {
+        let entry = self
+            .hosts
+            .entry(crate::state::host_key(&result.host).to_owned())
+            .or_default();
+        match result.status {
+            crate::ferro::Status::Ok | crate::ferro::Status::Changed => {
+                if !entry.completed.contains(&result.task) {
+                    entry.completed.push(result.task.clone());
+                }
+                entry.redacted.retain(|t| t != &result.task);
+                if redacted {
+                    entry.redacted.push(result.task.clone());
+                }
+            }
+            crate::ferro::Status::Failed => entry.failed = Some(result.task.clone()),
+            crate::ferro::Status::Skipped => (),
+        }
+        entry.state = state.clone();
+        self.save()
+    }
+
+    pub fn save(&self) -> Result<(), io::Error> {
+        match &self.path {
+            Some(path) => {
+                let contents = serde_json::to_vec_pretty(self)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                fs::write(path, contents)
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn remove(&self) -> Result<(), io::Error> {
+        match &self.path {
+            Some(path) => match fs::remove_file(path) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let task = crate::ferro::Task {
+            description: "deploy".to_owned(),
+            ..Default::default()
+        };
+        let context = crate::ferro::Context::default();
+        let mut journal = Journal::default();
+        journal.begin(&None, false);
+        let skipped = crate::ferro::Task {
+            description: "deploy".to_owned(),
+            when: Box::new(crate::when::Never),
+            ..Default::default()
+        };
+        journal
+            .record(&skipped.run(&context), &HashMap::new(), false)
+            .unwrap();
+        assert!(journal.entry(&None).unwrap().completed.is_empty());
+
+        journal
+            .record(&task.run(&context), &HashMap::new(), true)
+            .unwrap();
+        assert_eq!(journal.entry(&None).unwrap().completed, vec!["deploy"]);
+        assert_eq!(journal.entry(&None).unwrap().redacted, vec!["deploy"]);
+        journal
+            .record(&task.run(&context), &HashMap::new(), false)
+            .unwrap();
+        assert!(journal.entry(&None).unwrap().redacted.is_empty());
+
+        journal.begin(&None, true);
+        assert_eq!(journal.entry(&None).unwrap().completed, vec!["deploy"]);
+        journal.begin(&None, false);
+        assert!(journal.entry(&None).unwrap().completed.is_empty());
+    }
+}
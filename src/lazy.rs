@@ -3,55 +3,114 @@ use serde_json::value::Value;
 #[macro_export]
 macro_rules! lazy_format {
     ($s:expr, $($arg:expr),*) => {
-        |context| format!($s, $($arg(context)),*)
+        |context| -> Result<std::string::String, $crate::lazy::Error> {
+            Ok(format!($s, $($arg(context)?),*))
+        }
     };
 }
 
-pub fn var(path: std::string::String) -> impl Fn(&crate::ferro::Context) -> std::string::String {
+pub fn var(
+    path: std::string::String,
+) -> impl Fn(&crate::ferro::Context) -> Result<std::string::String, Error> {
     move |context| {
         if let Some(value) = context.vars.get(&path) {
-            value.to_owned()
+            Ok(value.to_owned())
         } else {
-            "".to_owned()
+            Ok("".to_owned())
         }
     }
 }
 
+// Reads the output of a task earlier in this run. When tasks run in parallel
+// the reading task must list the task it reads in depends_on.
 pub fn state(
     task_description: std::string::String,
     path: std::string::String,
-) -> impl Fn(&crate::ferro::Context) -> std::string::String {
-    move |context| {
-        if let Some(task) = context.state.get(&task_description) {
-            if let Ok(Value::String(value)) = crate::ferro::find(&path, task) {
-                value.to_owned()
-            } else {
-                "".to_owned()
-            }
+) -> impl Fn(&crate::ferro::Context) -> Result<std::string::String, Error> {
+    move |context| Ok(find(&context.state, &task_description, &path))
+}
+
+// Reads an output persisted by a previous run through the state backend.
+pub fn previous_state(
+    task_description: std::string::String,
+    path: std::string::String,
+) -> impl Fn(&crate::ferro::Context) -> Result<std::string::String, Error> {
+    move |context| Ok(find(&context.previous_state, &task_description, &path))
+}
+
+fn find(
+    state: &std::collections::HashMap<std::string::String, Value>,
+    task_description: &str,
+    path: &str,
+) -> std::string::String {
+    if let Some(task) = state.get(task_description) {
+        if let Ok(Value::String(value)) = crate::ferro::find(path, task) {
+            value.to_owned()
         } else {
             "".to_owned()
         }
+    } else {
+        "".to_owned()
     }
 }
 
+// A value that could not be evaluated. Modules propagate it with `?` so that
+// the task fails instead of acting on a value that was silently left empty.
+#[derive(Debug)]
+pub struct Error(pub std::string::String);
+
 pub fn with_default(
-    f: impl Fn(&crate::ferro::Context) -> std::string::String,
-    default: impl Fn(&crate::ferro::Context) -> std::string::String,
-) -> impl Fn(&crate::ferro::Context) -> std::string::String {
+    f: impl Fn(&crate::ferro::Context) -> Result<std::string::String, Error>,
+    default: impl Fn(&crate::ferro::Context) -> Result<std::string::String, Error>,
+) -> impl Fn(&crate::ferro::Context) -> Result<std::string::String, Error> {
     move |context| {
-        let value = f(context);
+        let value = f(context)?;
         if value != "" {
-            value
+            Ok(value)
         } else {
             default(context)
         }
     }
 }
 
-pub fn string(s: std::string::String) -> impl Fn(&crate::ferro::Context) -> std::string::String {
-    move |_context| s.to_owned()
+pub fn template(
+    template: std::string::String,
+) -> impl Fn(&crate::ferro::Context) -> Result<std::string::String, Error> {
+    move |context| {
+        crate::template::render_context(&template, context).map_err(|e| Error(e.to_string()))
+    }
+}
+
+pub fn secret(
+    name: std::string::String,
+) -> impl Fn(&crate::ferro::Context) -> Result<std::string::String, Error> {
+    move |context| {
+        Ok(context
+            .secrets
+            .get(&name)
+            .map_or("".to_owned(), |s| s.expose().to_owned()))
+    }
+}
+
+pub fn lookup(
+    source: std::string::String,
+    key: std::string::String,
+) -> impl Fn(&crate::ferro::Context) -> Result<std::string::String, Error> {
+    move |context| {
+        context
+            .lookups
+            .get(&source, &key)
+            .map_err(|e| Error(e.to_string()))
+    }
+}
+
+pub fn string(
+    s: std::string::String,
+) -> impl Fn(&crate::ferro::Context) -> Result<std::string::String, Error> {
+    move |_context| Ok(s.to_owned())
 }
 
-pub type String = dyn Fn(&crate::ferro::Context) -> std::string::String;
+pub type String =
+    dyn Fn(&crate::ferro::Context) -> Result<std::string::String, Error> + Send + Sync;
 
-pub type Vec<T> = dyn Fn(&crate::ferro::Context) -> std::vec::Vec<T>;
+pub type Vec<T> = dyn Fn(&crate::ferro::Context) -> Result<std::vec::Vec<T>, Error> + Send + Sync;
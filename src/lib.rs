@@ -1,7 +1,18 @@
 #[macro_use]
 pub mod lazy;
 
+pub mod cli;
 pub mod command;
+pub mod connection;
 pub mod ferro;
+pub mod include;
+pub mod inventory;
+pub mod journal;
+pub mod lookup;
 pub mod modules;
+pub mod reporter;
+pub mod role;
+pub mod state;
+pub mod template;
+pub mod vault;
 pub mod when;
@@ -0,0 +1,176 @@
+use std::default::Default;
+use std::env;
+
+use rusoto_cloudformation::{CloudFormation, CloudFormationClient, ListExportsInput};
+use rusoto_core::Region;
+use rusoto_secretsmanager::{GetSecretValueRequest, SecretsManager as _, SecretsManagerClient};
+use rusoto_ssm::{GetParameterRequest, Ssm as _, SsmClient};
+
+use super::{Error, Lookup};
+
+pub const SSM: &str = "ssm";
+pub const SECRETS_MANAGER: &str = "secretsmanager";
+pub const CLOUDFORMATION_EXPORT: &str = "cloudformation_export";
+
+pub const ENDPOINT_URL: &str = "AWS_ENDPOINT_URL";
+
+const FIELD_SEPARATOR: char = '#';
+const SECURE_STRING: &str = "SecureString";
+
+pub fn region() -> Region {
+    match env::var(ENDPOINT_URL) {
+        Ok(endpoint) => endpoint_region(&endpoint),
+        Err(_) => Region::default(),
+    }
+}
+
+pub fn endpoint_region(endpoint: &str) -> Region {
+    Region::Custom {
+        name: Region::default().name().to_owned(),
+        endpoint: endpoint.to_owned(),
+    }
+}
+
+pub struct Ssm {
+    pub region: Region,
+}
+
+impl Ssm {
+    pub fn new(region: Region) -> Self {
+        Ssm { region: region }
+    }
+}
+
+impl Lookup for Ssm {
+    fn lookup(&self, key: &str) -> Result<String, Error> {
+        self.lookup_secret(key).map(|(value, _)| value)
+    }
+
+    // Only SecureString parameters are redacted; String and StringList
+    // parameters are plain configuration.
+    fn lookup_secret(&self, key: &str) -> Result<(String, bool), Error> {
+        let output = SsmClient::new(self.region.clone())
+            .get_parameter(GetParameterRequest {
+                name: key.to_owned(),
+                with_decryption: Some(true),
+            })
+            .sync()
+            .map_err(|e| Error::AwsError(format!("{}: {}", key, e)))?;
+        let parameter = output
+            .parameter
+            .ok_or_else(|| Error::NotFoundError(format!("parameter {}", key)))?;
+        let secure = parameter.type_.as_deref() == Some(SECURE_STRING);
+        parameter
+            .value
+            .map(|value| (value, secure))
+            .ok_or_else(|| Error::NotFoundError(format!("parameter {}", key)))
+    }
+}
+
+pub struct SecretsManager {
+    pub region: Region,
+}
+
+impl SecretsManager {
+    pub fn new(region: Region) -> Self {
+        SecretsManager { region: region }
+    }
+}
+
+impl Lookup for SecretsManager {
+    fn lookup(&self, key: &str) -> Result<String, Error> {
+        let (secret_id, field) = match key.find(FIELD_SEPARATOR) {
+            Some(index) => (&key[..index], Some(&key[index + 1..])),
+            None => (key, None),
+        };
+        let output = SecretsManagerClient::new(self.region.clone())
+            .get_secret_value(GetSecretValueRequest {
+                secret_id: secret_id.to_owned(),
+                ..Default::default()
+            })
+            .sync()
+            .map_err(|e| Error::AwsError(format!("{}: {}", secret_id, e)))?;
+        let secret = output
+            .secret_string
+            .ok_or_else(|| Error::NotFoundError(format!("secret string of {}", secret_id)))?;
+        match field {
+            Some(field) => secret_field(&secret, field)
+                .ok_or_else(|| Error::NotFoundError(format!("{} in secret {}", field, secret_id))),
+            None => Ok(secret),
+        }
+    }
+
+    fn secret(&self) -> bool {
+        true
+    }
+}
+
+fn secret_field(secret: &str, field: &str) -> Option<String> {
+    serde_json::from_str::<serde_json::value::Value>(secret)
+        .ok()
+        .and_then(|v| v.get(field).map(crate::inventory::value_to_string))
+}
+
+pub struct Exports {
+    pub region: Region,
+}
+
+impl Exports {
+    pub fn new(region: Region) -> Self {
+        Exports { region: region }
+    }
+}
+
+impl Lookup for Exports {
+    fn lookup(&self, key: &str) -> Result<String, Error> {
+        let client = CloudFormationClient::new(self.region.clone());
+        let mut next_token = None;
+        loop {
+            let output = client
+                .list_exports(ListExportsInput {
+                    next_token: next_token,
+                })
+                .sync()
+                .map_err(|e| Error::AwsError(format!("{}: {}", key, e)))?;
+            let value = output
+                .exports
+                .unwrap_or_default()
+                .into_iter()
+                .find(|e| e.name.as_ref().map_or(false, |n| n == key))
+                .and_then(|e| e.value);
+            if let Some(value) = value {
+                return Ok(value);
+            }
+            next_token = output.next_token;
+            if next_token.is_none() {
+                return Err(Error::NotFoundError(format!("export {}", key)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_field() {
+        let secret = r#"{"username": "admin", "port": 5432}"#;
+        assert_eq!(secret_field(secret, "username"), Some("admin".to_owned()));
+        assert_eq!(secret_field(secret, "port"), Some("5432".to_owned()));
+        assert_eq!(secret_field(secret, "missing"), None);
+        assert_eq!(secret_field("plain", "username"), None);
+    }
+
+    // Requires a local AWS endpoint such as localstack with the SecureString
+    // parameter /ferro/test set to "value" and the String parameter
+    // /ferro/plain set to "plain", e.g. AWS_ENDPOINT_URL=http://localhost:4566.
+    #[test]
+    #[ignore]
+    fn test_ssm_local_endpoint() {
+        let lookups = crate::lookup::Lookups::default();
+        assert_eq!(lookups.get(SSM, "/ferro/test").unwrap(), "value");
+        assert_eq!(lookups.get(SSM, "/ferro/plain").unwrap(), "plain");
+        assert_eq!(lookups.secrets(), vec!["value"]);
+    }
+}
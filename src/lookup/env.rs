@@ -0,0 +1,13 @@
+use std::env;
+
+use super::{Error, Lookup};
+
+pub const ENV: &str = "env";
+
+pub struct Env;
+
+impl Lookup for Env {
+    fn lookup(&self, key: &str) -> Result<String, Error> {
+        env::var(key).map_err(|_| Error::NotFoundError(format!("environment variable {}", key)))
+    }
+}
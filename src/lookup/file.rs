@@ -0,0 +1,15 @@
+use std::fs;
+
+use super::{Error, Lookup};
+
+pub const FILE: &str = "file";
+
+pub struct File;
+
+impl Lookup for File {
+    fn lookup(&self, key: &str) -> Result<String, Error> {
+        fs::read_to_string(key)
+            .map(|contents| contents.trim_end_matches(&['\r', '\n'][..]).to_owned())
+            .map_err(|e| Error::IoError(format!("{}: {}", key, e)))
+    }
+}
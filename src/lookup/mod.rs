@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+pub mod aws;
+pub mod env;
+pub mod file;
+pub mod pipe;
+
+#[derive(Debug)]
+pub enum Error {
+    UnknownSourceError(String),
+    NotFoundError(String),
+    IoError(String),
+    CommandError(String),
+    AwsError(String),
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UnknownSourceError(e) => write!(f, "unknown lookup source {}", e),
+            Error::NotFoundError(e) => write!(f, "lookup value not found: {}", e),
+            Error::IoError(e) => write!(f, "lookup failed: {}", e),
+            Error::CommandError(e) => write!(f, "lookup command failed: {}", e),
+            Error::AwsError(e) => write!(f, "aws lookup failed: {}", e),
+        }
+    }
+}
+
+pub trait Lookup: Send + Sync {
+    fn lookup(&self, key: &str) -> Result<String, Error>;
+
+    fn secret(&self) -> bool {
+        false
+    }
+
+    // The value and whether it must be redacted. Sources that only know
+    // this per value, such as SSM parameter types, override this.
+    fn lookup_secret(&self, key: &str) -> Result<(String, bool), Error> {
+        self.lookup(key).map(|value| (value, self.secret()))
+    }
+}
+
+#[derive(Default)]
+struct Cache {
+    values: HashMap<(String, String), String>,
+    secrets: Vec<String>,
+}
+
+pub struct Lookups {
+    sources: HashMap<String, Arc<dyn Lookup>>,
+    cache: Mutex<Cache>,
+}
+
+impl Default for Lookups {
+    fn default() -> Self {
+        let mut lookups = Lookups {
+            sources: HashMap::new(),
+            cache: Mutex::new(Cache::default()),
+        };
+        lookups.register(file::FILE, Arc::new(file::File));
+        lookups.register(env::ENV, Arc::new(env::Env));
+        lookups.register(pipe::PIPE, Arc::new(pipe::Pipe));
+        lookups.register(aws::SSM, Arc::new(aws::Ssm::new(aws::region())));
+        lookups.register(
+            aws::SECRETS_MANAGER,
+            Arc::new(aws::SecretsManager::new(aws::region())),
+        );
+        lookups.register(
+            aws::CLOUDFORMATION_EXPORT,
+            Arc::new(aws::Exports::new(aws::region())),
+        );
+        lookups
+    }
+}
+
+impl Lookups {
+    pub fn register(&mut self, name: &str, source: Arc<dyn Lookup>) {
+        self.sources.insert(name.to_owned(), source);
+    }
+
+    pub fn get(&self, source: &str, key: &str) -> Result<String, Error> {
+        let cache_key = (source.to_owned(), key.to_owned());
+        if let Some(value) = self.cache.lock().unwrap().values.get(&cache_key) {
+            return Ok(value.clone());
+        }
+        let lookup = self
+            .sources
+            .get(source)
+            .ok_or_else(|| Error::UnknownSourceError(source.to_owned()))?;
+        let (value, secret) = lookup.lookup_secret(key)?;
+
+        let mut cache = self.cache.lock().unwrap();
+        if secret && !cache.secrets.contains(&value) {
+            cache.secrets.push(value.clone());
+        }
+        cache.values.insert(cache_key, value.clone());
+        Ok(value)
+    }
+
+    pub fn secrets(&self) -> Vec<String> {
+        self.cache.lock().unwrap().secrets.clone()
+    }
+
+    pub fn clear(&self) {
+        *self.cache.lock().unwrap() = Cache::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Counter(AtomicUsize);
+
+    impl Lookup for Counter {
+        fn lookup(&self, key: &str) -> Result<String, Error> {
+            let count = self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(format!("{}-{}", key, count))
+        }
+
+        fn secret(&self) -> bool {
+            true
+        }
+    }
+
+    struct Typed;
+
+    impl Lookup for Typed {
+        fn lookup(&self, key: &str) -> Result<String, Error> {
+            self.lookup_secret(key).map(|(value, _)| value)
+        }
+
+        fn lookup_secret(&self, key: &str) -> Result<(String, bool), Error> {
+            Ok((key.to_owned(), key.starts_with("secure")))
+        }
+    }
+
+    #[test]
+    fn test_lookup_secret() {
+        let mut lookups = Lookups::default();
+        lookups.register("typed", Arc::new(Typed));
+        lookups.get("typed", "plain").unwrap();
+        lookups.get("typed", "secure-value").unwrap();
+        assert_eq!(lookups.secrets(), vec!["secure-value"]);
+    }
+
+    #[test]
+    fn test_lookup_failure_fails_task() {
+        let task = crate::ferro::Task {
+            description: "lookup".to_owned(),
+            module: Box::new(crate::modules::shell::Shell {
+                script: Box::new(crate::lazy::lookup("bogus".to_owned(), "key".to_owned())),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let result = task.run(&Default::default());
+        assert_eq!(result.status, crate::ferro::Status::Failed);
+        assert_eq!(result.error, Some("unknown lookup source bogus".to_owned()));
+    }
+
+    #[test]
+    fn test_lookups() {
+        let mut lookups = Lookups::default();
+        lookups.register("counter", Arc::new(Counter(AtomicUsize::new(0))));
+
+        assert_eq!(lookups.get("counter", "a").unwrap(), "a-0");
+        assert_eq!(lookups.get("counter", "a").unwrap(), "a-0");
+        assert_eq!(lookups.get("counter", "b").unwrap(), "b-1");
+        assert_eq!(lookups.secrets(), vec!["a-0", "b-1"]);
+        lookups.clear();
+        assert_eq!(lookups.get("counter", "a").unwrap(), "a-2");
+
+        std::env::set_var("FERRO_LOOKUP_TEST", "from env");
+        assert_eq!(lookups.get("env", "FERRO_LOOKUP_TEST").unwrap(), "from env");
+        assert_eq!(
+            lookups.get("pipe", "echo 'hello world'").unwrap(),
+            "hello world"
+        );
+        assert!(lookups.get("pipe", "/bin/false").is_err());
+        assert!(lookups.get("file", "/nonexistent/ferro").is_err());
+        assert!(lookups.get("bogus", "key").is_err());
+    }
+}
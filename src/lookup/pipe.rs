@@ -0,0 +1,32 @@
+use std::process;
+
+use super::{Error, Lookup};
+
+pub const PIPE: &str = "pipe";
+
+pub struct Pipe;
+
+impl Lookup for Pipe {
+    fn lookup(&self, key: &str) -> Result<String, Error> {
+        let mut words = shell_words::split(key)
+            .map_err(|e| Error::CommandError(format!("{}: {}", key, e)))?
+            .into_iter();
+        let command = words
+            .next()
+            .ok_or_else(|| Error::CommandError("empty command".to_owned()))?;
+        let output = process::Command::new(command)
+            .args(words)
+            .stdin(process::Stdio::null())
+            .output()
+            .map_err(|e| Error::CommandError(format!("{}: {}", key, e)))?;
+        if !output.status.success() {
+            return Err(Error::CommandError(format!(
+                "`{}` exited with {}",
+                key, output.status
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .trim_end_matches(&['\r', '\n'][..])
+            .to_owned())
+    }
+}
@@ -1,3 +1,3 @@
 fn main() {
-    println!("Hello, world!");
+    ferro::cli::main_from_file();
 }
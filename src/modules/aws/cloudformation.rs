@@ -5,12 +5,17 @@ use std::error;
 use std::fmt;
 use std::str::FromStr;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use rusoto_cloudformation::{
-    CloudFormation as CF, CloudFormationClient, CreateStackError, CreateStackInput,
-    DescribeStacksError, DescribeStacksInput, Output as CFOutput, Stack, UpdateStackError,
-    UpdateStackInput,
+    CloudFormation as CF, CloudFormationClient, CreateChangeSetInput, CreateStackError,
+    CreateStackInput, DeleteChangeSetInput, DeleteStackInput, DescribeChangeSetInput,
+    DescribeChangeSetOutput, DescribeStackDriftDetectionStatusInput,
+    DescribeStackDriftDetectionStatusOutput, DescribeStackEventsInput,
+    DescribeStackResourceDriftsInput, DescribeStacksError, DescribeStacksInput,
+    DetectStackDriftInput, ExecuteChangeSetInput, GetStackPolicyInput, ListStackResourcesInput,
+    Output as CFOutput, ResourceToImport, RollbackConfiguration, RollbackTrigger,
+    SetStackPolicyInput, Stack, StackEvent, StackResourceDrift, UpdateStackError, UpdateStackInput,
 };
 use rusoto_core::RusotoError;
 use serde::{Deserialize, Serialize};
@@ -27,12 +32,50 @@ const DELETE_COMPLETE: &str = "DELETE_COMPLETE";
 const DELETE_FAILED: &str = "DELETE_FAILED";
 const ROLLBACK_FAILED: &str = "ROLLBACK_FAILED";
 const ROLLBACK_COMPLETE: &str = "ROLLBACK_COMPLETE";
+const REVIEW_IN_PROGRESS: &str = "REVIEW_IN_PROGRESS";
 
 const UPDATE_COMPLETE: &str = "UPDATE_COMPLETE";
 const UPDATE_FAILED: &str = "UPDATE_FAILED";
 const UPDATE_ROLLBACK_FAILED: &str = "UPDATE_ROLLBACK_FAILED";
 const UPDATE_ROLLBACK_COMPLETE: &str = "UPDATE_ROLLBACK_COMPLETE";
 
+const IMPORT_COMPLETE: &str = "IMPORT_COMPLETE";
+const IMPORT_ROLLBACK_FAILED: &str = "IMPORT_ROLLBACK_FAILED";
+const IMPORT_ROLLBACK_COMPLETE: &str = "IMPORT_ROLLBACK_COMPLETE";
+
+const CHANGE_SET_IMPORT: &str = "IMPORT";
+const CHANGE_SET_CREATE_COMPLETE: &str = "CREATE_COMPLETE";
+const CHANGE_SET_FAILED: &str = "FAILED";
+
+const DETECTION_COMPLETE: &str = "DETECTION_COMPLETE";
+const DETECTION_FAILED: &str = "DETECTION_FAILED";
+const DRIFTED: &str = "DRIFTED";
+const DRIFT_MODIFIED: &str = "MODIFIED";
+const DRIFT_DELETED: &str = "DELETED";
+
+const ON_FAILURE_ROLLBACK: &str = "ROLLBACK";
+const ON_FAILURE_DELETE: &str = "DELETE";
+const ON_FAILURE_DO_NOTHING: &str = "DO_NOTHING";
+
+const CLOUDWATCH_ALARM: &str = "AWS::CloudWatch::Alarm";
+const NESTED_STACK: &str = "AWS::CloudFormation::Stack";
+
+// Statuses the stack itself reports when an operation starts; a rollback
+// only starts an operation when it directly follows the previous one's end.
+const OPERATION_START: [&str; 4] = [
+    "CREATE_IN_PROGRESS",
+    "UPDATE_IN_PROGRESS",
+    "IMPORT_IN_PROGRESS",
+    REVIEW_IN_PROGRESS,
+];
+const ROLLBACK_START: [&str; 3] = [
+    "ROLLBACK_IN_PROGRESS",
+    "UPDATE_ROLLBACK_IN_PROGRESS",
+    "IMPORT_ROLLBACK_IN_PROGRESS",
+];
+const IN_PROGRESS_SUFFIX: &str = "_IN_PROGRESS";
+const FAILED_SUFFIX: &str = "_FAILED";
+
 const SLEEP_SECS: u64 = 5;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -82,7 +125,13 @@ impl From<RusotoError<UpdateStackError>> for Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self)
+        match self {
+            Error::CloudFormationError(e) => write!(f, "{}", e),
+            Error::StackNotFoundError => write!(f, "stack not found"),
+            Error::RegionNotFoundError => write!(f, "region not found"),
+            Error::NoUpdateError => write!(f, "no updates are to be performed"),
+            Error::UnknownError => write!(f, "unknown error"),
+        }
     }
 }
 
@@ -91,9 +140,126 @@ pub enum Template {
     TemplateURL(String),
 }
 
+// An existing resource to adopt, identified by the properties its resource
+// type uses as identifier (e.g. `BucketName` for `AWS::S3::Bucket`).
+#[derive(Clone, Debug)]
+pub struct Import {
+    pub resource_type: String,
+    pub identifier: HashMap<String, String>,
+}
+
+// What CloudFormation does with a stack that fails to create. `DoNothing`
+// is equivalent to `DisableRollback`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OnFailure {
+    Rollback,
+    Delete,
+    DoNothing,
+}
+
+impl Default for OnFailure {
+    fn default() -> Self {
+        OnFailure::Rollback
+    }
+}
+
+impl OnFailure {
+    fn as_str(&self) -> &str {
+        match self {
+            OnFailure::Rollback => ON_FAILURE_ROLLBACK,
+            OnFailure::Delete => ON_FAILURE_DELETE,
+            OnFailure::DoNothing => ON_FAILURE_DO_NOTHING,
+        }
+    }
+}
+
+struct StackOptions {
+    stack_policy: String,
+    stack_policy_during_update: String,
+    rollback: Option<RollbackConfiguration>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DriftDetection {
+    Disabled,
+    Report,
+    Fail,
+}
+
+impl Default for DriftDetection {
+    fn default() -> Self {
+        DriftDetection::Disabled
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PropertyDifference {
+    property_path: String,
+    expected_value: String,
+    actual_value: String,
+    difference_type: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DriftedResource {
+    logical_resource_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    physical_resource_id: Option<String>,
+    resource_type: String,
+    status: String,
+    differences: Vec<PropertyDifference>,
+}
+
+impl From<StackResourceDrift> for DriftedResource {
+    fn from(drift: StackResourceDrift) -> Self {
+        DriftedResource {
+            logical_resource_id: drift.logical_resource_id,
+            physical_resource_id: drift.physical_resource_id,
+            resource_type: drift.resource_type,
+            status: drift.stack_resource_drift_status,
+            differences: drift
+                .property_differences
+                .unwrap_or_default()
+                .into_iter()
+                .map(|d| PropertyDifference {
+                    property_path: d.property_path,
+                    expected_value: d.expected_value,
+                    actual_value: d.actual_value,
+                    difference_type: d.difference_type,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Drift {
+    status: String,
+    resources: Vec<DriftedResource>,
+}
+
+impl Drift {
+    fn drifted(&self) -> bool {
+        self.status == DRIFTED
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct NestedStack {
+    stack_name: String,
+    status: String,
+    outputs: HashMap<String, String>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    nested: HashMap<String, NestedStack>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct Output {
     outputs: HashMap<String, String>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    nested: HashMap<String, NestedStack>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drift: Option<Drift>,
 }
 
 #[typetag::serialize]
@@ -105,7 +271,14 @@ impl crate::ferro::Output for Output {
 
 pub struct CloudFormation {
     pub stack_name: Box<crate::lazy::String>,
-    pub template: Box<dyn Fn(&crate::ferro::Context) -> Template>,
+    pub template: Box<dyn Fn(&crate::ferro::Context) -> Template + Send + Sync>,
+    pub drift_detection: DriftDetection,
+    pub stack_policy: Box<crate::lazy::String>,
+    pub stack_policy_during_update: Box<crate::lazy::String>,
+    pub rollback_alarms: Box<crate::lazy::Vec<String>>,
+    pub rollback_monitoring_minutes: Option<i64>,
+    pub on_failure: OnFailure,
+    pub imports: Box<dyn Fn(&crate::ferro::Context) -> HashMap<String, Import> + Send + Sync>,
     pub cfn: CloudFormationClient,
 }
 
@@ -152,6 +325,22 @@ impl CloudFormation {
         self.wait_for_stack(states, UPDATE_COMPLETE.to_owned(), stack_name)
     }
 
+    fn wait_for_stack_import(&self, stack_name: &String) -> Result<(), Error> {
+        let states = vec![
+            IMPORT_ROLLBACK_FAILED.to_owned(),
+            IMPORT_ROLLBACK_COMPLETE.to_owned(),
+        ];
+        self.wait_for_stack(states, IMPORT_COMPLETE.to_owned(), stack_name)
+    }
+
+    fn wait_for_stack_delete(&self, stack_id: &String) -> Result<(), Error> {
+        let states = vec![DELETE_FAILED.to_owned()];
+        match self.wait_for_stack(states, DELETE_COMPLETE.to_owned(), stack_id) {
+            Err(Error::StackNotFoundError) => Ok(()),
+            result => result,
+        }
+    }
+
     fn wait_for_stack(
         &self,
         states: Vec<String>,
@@ -163,20 +352,329 @@ impl CloudFormation {
             if stack.stack_status == desired_state {
                 return Ok(());
             } else if states.contains(&stack.stack_status) {
-                return Err(Error::CloudFormationError(stack.stack_status.to_owned()));
+                let stack_id = stack.stack_id.unwrap_or(stack.stack_name);
+                let reasons = self.failure_reasons(&stack_id)?;
+                return Err(Error::CloudFormationError(if reasons.is_empty() {
+                    stack.stack_status
+                } else {
+                    format!("{}: {}", stack.stack_status, reasons.join("; "))
+                }));
             } else {
                 sleep(Duration::from_secs(SLEEP_SECS));
             }
         }
     }
 
+    // UpdateStack reports no updates when only the stack policy differs, so
+    // the policy is compared and set on its own in that case.
+    fn sync_stack_policy(&self, stack_name: &String, policy: &String) -> Result<bool, Error> {
+        if policy == "" {
+            return Ok(false);
+        }
+        let current = self
+            .cfn
+            .get_stack_policy(GetStackPolicyInput {
+                stack_name: stack_name.to_owned(),
+            })
+            .sync()
+            .map_err(|e| Error::CloudFormationError(e.to_string()))?
+            .stack_policy_body;
+        if same_policy(current, policy) {
+            return Ok(false);
+        }
+        self.cfn
+            .set_stack_policy(SetStackPolicyInput {
+                stack_name: stack_name.to_owned(),
+                stack_policy_body: Some(policy.to_owned()),
+                ..Default::default()
+            })
+            .sync()
+            .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+        Ok(true)
+    }
+
+    fn options(&self, context: &crate::ferro::Context) -> Result<StackOptions, crate::lazy::Error> {
+        let alarms = (self.rollback_alarms)(context)?;
+        let rollback = if alarms.is_empty() && self.rollback_monitoring_minutes.is_none() {
+            None
+        } else {
+            Some(RollbackConfiguration {
+                monitoring_time_in_minutes: self.rollback_monitoring_minutes,
+                rollback_triggers: Some(
+                    alarms
+                        .into_iter()
+                        .map(|arn| RollbackTrigger {
+                            arn: arn,
+                            type_: CLOUDWATCH_ALARM.to_owned(),
+                        })
+                        .collect(),
+                ),
+            })
+        };
+        Ok(StackOptions {
+            stack_policy: (self.stack_policy)(context)?,
+            stack_policy_during_update: (self.stack_policy_during_update)(context)?,
+            rollback: rollback,
+        })
+    }
+
+    fn detect_drift(&self, stack_name: &String) -> Result<Drift, Error> {
+        let detection_id = self
+            .cfn
+            .detect_stack_drift(DetectStackDriftInput {
+                stack_name: stack_name.to_owned(),
+                ..Default::default()
+            })
+            .sync()
+            .map_err(|e| Error::CloudFormationError(e.to_string()))?
+            .stack_drift_detection_id;
+
+        let status = loop {
+            let status = self
+                .cfn
+                .describe_stack_drift_detection_status(DescribeStackDriftDetectionStatusInput {
+                    stack_drift_detection_id: detection_id.to_owned(),
+                })
+                .sync()
+                .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+            match drift_status(status)? {
+                Some(status) => break status,
+                None => sleep(Duration::from_secs(SLEEP_SECS)),
+            }
+        };
+
+        let mut resources = vec![];
+        let mut next_token = None;
+        loop {
+            let result = self
+                .cfn
+                .describe_stack_resource_drifts(DescribeStackResourceDriftsInput {
+                    stack_name: stack_name.to_owned(),
+                    stack_resource_drift_status_filters: Some(vec![
+                        DRIFT_MODIFIED.to_owned(),
+                        DRIFT_DELETED.to_owned(),
+                    ]),
+                    next_token: next_token,
+                    ..Default::default()
+                })
+                .sync()
+                .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+            resources.extend(
+                result
+                    .stack_resource_drifts
+                    .into_iter()
+                    .map(DriftedResource::from),
+            );
+            next_token = result.next_token;
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(Drift {
+            status: status,
+            resources: resources,
+        })
+    }
+
+    // Imports that are not yet part of the stack; an empty map means the
+    // stack can be created or updated normally.
+    fn pending_imports(
+        &self,
+        stack_name: &String,
+        imports: HashMap<String, Import>,
+    ) -> Result<HashMap<String, Import>, Error> {
+        collect_pending_imports(imports, &|next_token| {
+            let result = self
+                .cfn
+                .list_stack_resources(ListStackResourcesInput {
+                    stack_name: stack_name.to_owned(),
+                    next_token: next_token,
+                })
+                .sync()
+                .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+            let logical_ids = result
+                .stack_resource_summaries
+                .unwrap_or_default()
+                .into_iter()
+                .map(|resource| resource.logical_resource_id)
+                .collect();
+            Ok((logical_ids, result.next_token))
+        })
+    }
+
+    // Imports always roll back on failure; for a stack created by the import,
+    // `OnFailure::Delete` also deletes the stack left behind.
+    fn import_resources(
+        &self,
+        stack_name: &String,
+        template: &Template,
+        imports: HashMap<String, Import>,
+        options: &StackOptions,
+        new_stack: bool,
+    ) -> Result<Option<Output>, Error> {
+        let change_set_name = format!(
+            "ferro-import-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+        );
+        self.cfn
+            .create_change_set(import_change_set_input(
+                stack_name,
+                &change_set_name,
+                template,
+                imports,
+                options,
+            ))
+            .sync()
+            .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+
+        wait_for_change_set(
+            &|| {
+                self.cfn
+                    .describe_change_set(DescribeChangeSetInput {
+                        stack_name: Some(stack_name.to_owned()),
+                        change_set_name: change_set_name.to_owned(),
+                        ..Default::default()
+                    })
+                    .sync()
+                    .map_err(|e| Error::CloudFormationError(e.to_string()))
+            },
+            &|| {
+                self.cfn
+                    .delete_change_set(DeleteChangeSetInput {
+                        stack_name: Some(stack_name.to_owned()),
+                        change_set_name: change_set_name.to_owned(),
+                    })
+                    .sync()
+                    .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+                self.delete_failed_import(stack_name, new_stack)
+            },
+        )?;
+
+        self.cfn
+            .execute_change_set(ExecuteChangeSetInput {
+                stack_name: Some(stack_name.to_owned()),
+                change_set_name: change_set_name,
+                ..Default::default()
+            })
+            .sync()
+            .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+
+        if let Err(e) = self.wait_for_stack_import(stack_name) {
+            self.delete_failed_import(stack_name, new_stack)?;
+            return Err(e);
+        }
+        self.sync_stack_policy(stack_name, &options.stack_policy)
+            .and_then(|_| self.get_stack_info(stack_name))
+            .and_then(|stack| self.stack_output(stack))
+            .map_err(|e| Error::CloudFormationError(e.to_string()))
+    }
+
+    fn delete_failed_import(&self, stack_name: &String, new_stack: bool) -> Result<(), Error> {
+        if !new_stack || self.on_failure != OnFailure::Delete {
+            return Ok(());
+        }
+        self.cfn
+            .delete_stack(DeleteStackInput {
+                stack_name: stack_name.to_owned(),
+                ..Default::default()
+            })
+            .sync()
+            .map_err(|e| Error::CloudFormationError(e.to_string()))
+    }
+
+    // Deleted stacks are only described by id, so the stack id is used to
+    // wait for the deletion to finish.
+    fn delete_stack(&self, stack_id: &String) -> Result<(), Error> {
+        self.cfn
+            .delete_stack(DeleteStackInput {
+                stack_name: stack_id.to_owned(),
+                ..Default::default()
+            })
+            .sync()
+            .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+        self.wait_for_stack_delete(stack_id)
+    }
+
+    // Nested stacks are walked on every run, so a run without changes reports
+    // the same outputs as the run that created them.
+    fn stack_output(&self, stack: Stack) -> Result<Option<Output>, Error> {
+        let stack_id = stack.stack_id.unwrap_or(stack.stack_name);
+        let nested = self.nested_stacks(&stack_id)?;
+        if stack.outputs.is_none() && nested.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Output {
+            outputs: outputs_to_map(stack.outputs.unwrap_or_default()),
+            nested: nested,
+            drift: None,
+        }))
+    }
+
+    fn nested_stacks(&self, stack_id: &String) -> Result<HashMap<String, NestedStack>, Error> {
+        let mut nested = HashMap::new();
+        let mut next_token = None;
+        loop {
+            let result = self
+                .cfn
+                .list_stack_resources(ListStackResourcesInput {
+                    stack_name: stack_id.to_owned(),
+                    next_token: next_token,
+                })
+                .sync()
+                .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+            for resource in result.stack_resource_summaries.unwrap_or_default() {
+                if resource.resource_type != NESTED_STACK {
+                    continue;
+                }
+                if let Some(physical_id) = resource.physical_resource_id {
+                    let stack = self.get_stack_info(&physical_id)?;
+                    nested.insert(
+                        resource.logical_resource_id,
+                        NestedStack {
+                            stack_name: stack.stack_name,
+                            status: stack.stack_status,
+                            outputs: outputs_to_map(stack.outputs.unwrap_or_default()),
+                            nested: self.nested_stacks(&physical_id)?,
+                        },
+                    );
+                }
+            }
+            next_token = result.next_token;
+            if next_token.is_none() {
+                return Ok(nested);
+            }
+        }
+    }
+
+    fn failure_reasons(&self, stack_id: &String) -> Result<Vec<String>, Error> {
+        collect_failure_reasons(stack_id, "", &|stack_id, next_token| {
+            let result = self
+                .cfn
+                .describe_stack_events(DescribeStackEventsInput {
+                    stack_name: Some(stack_id.to_owned()),
+                    next_token: next_token,
+                })
+                .sync()
+                .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+            Ok((result.stack_events.unwrap_or_default(), result.next_token))
+        })
+    }
+
     fn create_stack(
         &self,
         stack_name: &String,
         template: &Template,
+        options: &StackOptions,
     ) -> Result<Option<Output>, Error> {
         let mut create_stack_input = CreateStackInput {
             stack_name: stack_name.to_owned(),
+            on_failure: Some(self.on_failure.as_str().to_owned()),
+            stack_policy_body: non_empty(&options.stack_policy),
+            rollback_configuration: options.rollback.clone(),
             capabilities: Some(vec![
                 CAPABILITY_IAM.to_owned(),
                 CAPABILITY_NAMED_IAM.to_owned(),
@@ -191,17 +689,18 @@ impl CloudFormation {
             Template::TemplateURL(url) => create_stack_input.template_url = Some(url.to_owned()),
         };
 
-        self.cfn.create_stack(create_stack_input).sync()?;
+        // Polling by id rather than name still finds the stack once
+        // `OnFailure::Delete` has removed it.
+        let stack_id = self
+            .cfn
+            .create_stack(create_stack_input)
+            .sync()?
+            .stack_id
+            .unwrap_or(stack_name.to_owned());
 
-        self.wait_for_stack_create(stack_name)
-            .and_then(|_| self.get_stack_info(stack_name))
-            .and_then(|stack| {
-                stack.outputs.map_or(Ok(None), |outputs| {
-                    Ok(Some(Output {
-                        outputs: outputs_to_map(outputs),
-                    }))
-                })
-            })
+        self.wait_for_stack_create(&stack_id)
+            .and_then(|_| self.get_stack_info(&stack_id))
+            .and_then(|stack| self.stack_output(stack))
             .map_err(|e| Error::CloudFormationError(e.to_string()))
     }
 
@@ -209,9 +708,13 @@ impl CloudFormation {
         &self,
         stack_name: &String,
         template: &Template,
+        options: &StackOptions,
     ) -> Result<Option<Output>, Error> {
         let mut update_stack_input = UpdateStackInput {
             stack_name: stack_name.to_owned(),
+            stack_policy_body: non_empty(&options.stack_policy),
+            stack_policy_during_update_body: non_empty(&options.stack_policy_during_update),
+            rollback_configuration: options.rollback.clone(),
             capabilities: Some(vec![
                 CAPABILITY_IAM.to_owned(),
                 CAPABILITY_NAMED_IAM.to_owned(),
@@ -230,13 +733,7 @@ impl CloudFormation {
 
         self.wait_for_stack_update(stack_name)
             .and_then(|_| self.get_stack_info(stack_name))
-            .and_then(|stack| {
-                stack.outputs.map_or(Ok(None), |outputs| {
-                    Ok(Some(Output {
-                        outputs: outputs_to_map(outputs),
-                    }))
-                })
-            })
+            .and_then(|stack| self.stack_output(stack))
             .map_err(|e| Error::CloudFormationError(e.to_string()))
     }
 }
@@ -246,7 +743,14 @@ impl Default for CloudFormation {
         CloudFormation {
             stack_name: Box::new(crate::lazy::string("".to_owned())),
             template: Box::new(|_| Template::TemplateBody("".to_owned())),
-            cfn: CloudFormationClient::new(Default::default()),
+            drift_detection: Default::default(),
+            stack_policy: Box::new(crate::lazy::string("".to_owned())),
+            stack_policy_during_update: Box::new(crate::lazy::string("".to_owned())),
+            rollback_alarms: Box::new(|_| Ok(vec![])),
+            rollback_monitoring_minutes: None,
+            on_failure: Default::default(),
+            imports: Box::new(|_| HashMap::new()),
+            cfn: CloudFormationClient::new(crate::lookup::aws::region()),
         }
     }
 }
@@ -260,38 +764,102 @@ impl crate::ferro::Module for CloudFormation {
         &self,
         context: &crate::ferro::Context,
     ) -> Result<crate::ferro::Response, crate::ferro::Error> {
-        let stack_name = (self.stack_name)(context);
+        let stack_name = (self.stack_name)(context)?;
         let template = (self.template)(context);
-        match self.get_stack_info(&stack_name) {
-            Ok(_) => match self.update_stack(&stack_name, &template) {
-                Ok(opt) => opt.map_or_else(
-                    || crate::ferro::result_response(true, None),
-                    |output| crate::ferro::result_response(true, Some(Box::new(output))),
-                ),
-                Err(Error::NoUpdateError) => self
-                    .get_stack_info(&stack_name)
-                    .map_err(|e| crate::ferro::error(false, e.to_string()))
-                    .and_then(|stack| {
-                        stack.outputs.map_or_else(
-                            || crate::ferro::result_response(false, None),
-                            |outputs| {
-                                crate::ferro::result_response(
-                                    false,
-                                    Some(Box::new(Output {
-                                        outputs: outputs_to_map(outputs),
-                                    })),
-                                )
-                            },
-                        )
-                    }),
-                Err(e) => crate::ferro::result_error(true, e.to_string()),
-            },
+        let options = self.options(context)?;
+        let imports = (self.imports)(context);
+        // A new stack whose IMPORT change set failed is left empty in
+        // `REVIEW_IN_PROGRESS`; it is deleted and created again.
+        let existing = match self.get_stack_info(&stack_name) {
+            Ok(stack) if stack.stack_status == REVIEW_IN_PROGRESS => {
+                let stack_id = stack.stack_id.unwrap_or(stack.stack_name);
+                match self.delete_stack(&stack_id) {
+                    Ok(()) => Err(Error::StackNotFoundError),
+                    Err(e) => return crate::ferro::result_error(false, e.to_string()),
+                }
+            }
+            existing => existing,
+        };
+        match existing {
+            Ok(stack) => {
+                if failed_create(&stack.stack_status) {
+                    return crate::ferro::result_error(
+                        false,
+                        format!(
+                            "stack {} is {} and must be deleted before it can be updated",
+                            stack_name, stack.stack_status
+                        ),
+                    );
+                }
+                let drift = if self.drift_detection == DriftDetection::Disabled {
+                    None
+                } else {
+                    match self.detect_drift(&stack_name) {
+                        Ok(drift) => Some(drift),
+                        Err(e) => return crate::ferro::result_error(false, e.to_string()),
+                    }
+                };
+                if self.drift_detection == DriftDetection::Fail
+                    && drift.as_ref().map_or(false, |d| d.drifted())
+                {
+                    return crate::ferro::result_error_with_output(
+                        false,
+                        format!("stack {} has drifted", stack_name),
+                        Box::new(Output {
+                            outputs: HashMap::new(),
+                            nested: HashMap::new(),
+                            drift: drift,
+                        }),
+                    );
+                }
 
-            Err(Error::StackNotFoundError) => match self.create_stack(&stack_name, &template) {
-                Ok(Some(output)) => crate::ferro::result_response(true, Some(Box::new(output))),
-                Ok(None) => crate::ferro::result_response(true, None),
-                Err(e) => crate::ferro::result_error(true, e.to_string()),
-            },
+                let pending = match self.pending_imports(&stack_name, imports) {
+                    Ok(pending) => pending,
+                    Err(e) => return crate::ferro::result_error(false, e.to_string()),
+                };
+                let updated = if !pending.is_empty() {
+                    match self.import_resources(&stack_name, &template, pending, &options, false) {
+                        Ok(opt) => Ok((true, opt)),
+                        Err(e) => return crate::ferro::result_error(true, e.to_string()),
+                    }
+                } else {
+                    match self.update_stack(&stack_name, &template, &options) {
+                        Ok(opt) => Ok((true, opt)),
+                        Err(Error::NoUpdateError) => self
+                            .sync_stack_policy(&stack_name, &options.stack_policy)
+                            .and_then(|changed| {
+                                self.get_stack_info(&stack_name)
+                                    .map(|stack| (changed, stack))
+                            })
+                            .and_then(|(changed, stack)| {
+                                self.stack_output(stack).map(|output| (changed, output))
+                            }),
+                        Err(e) => return crate::ferro::result_error(true, e.to_string()),
+                    }
+                };
+                match updated {
+                    Ok((changed, output)) => match with_drift(output, drift) {
+                        Some(output) => {
+                            crate::ferro::result_response(changed, Some(Box::new(output)))
+                        }
+                        None => crate::ferro::result_response(changed, None),
+                    },
+                    Err(e) => crate::ferro::result_error(false, e.to_string()),
+                }
+            }
+
+            Err(Error::StackNotFoundError) => {
+                let created = if imports.is_empty() {
+                    self.create_stack(&stack_name, &template, &options)
+                } else {
+                    self.import_resources(&stack_name, &template, imports, &options, true)
+                };
+                match created {
+                    Ok(Some(output)) => crate::ferro::result_response(true, Some(Box::new(output))),
+                    Ok(None) => crate::ferro::result_response(true, None),
+                    Err(e) => crate::ferro::result_error(true, e.to_string()),
+                }
+            }
 
             Err(e) => crate::ferro::result_error(false, e.to_string()),
         }
@@ -314,3 +882,611 @@ fn outputs_to_map(outputs: Vec<CFOutput>) -> HashMap<String, String> {
     }
     map
 }
+
+// Walks the events of the most recent operation, newest first, and
+// follows failed nested stacks so the root cause is reported instead of
+// the parent's rollback status.
+// Removes the imports whose logical ids `list` reports as already part of
+// the stack, one page of logical ids at a time.
+fn collect_pending_imports(
+    imports: HashMap<String, Import>,
+    list: &dyn Fn(Option<String>) -> Result<(Vec<String>, Option<String>), Error>,
+) -> Result<HashMap<String, Import>, Error> {
+    if imports.is_empty() {
+        return Ok(imports);
+    }
+    let mut pending = imports;
+    let mut next_token = None;
+    loop {
+        let (logical_ids, token) = list(next_token)?;
+        for logical_id in logical_ids {
+            pending.remove(&logical_id);
+        }
+        next_token = token;
+        if next_token.is_none() || pending.is_empty() {
+            return Ok(pending);
+        }
+    }
+}
+
+fn import_change_set_input(
+    stack_name: &String,
+    change_set_name: &String,
+    template: &Template,
+    imports: HashMap<String, Import>,
+    options: &StackOptions,
+) -> CreateChangeSetInput {
+    let mut create_change_set_input = CreateChangeSetInput {
+        stack_name: stack_name.to_owned(),
+        change_set_name: change_set_name.to_owned(),
+        change_set_type: Some(CHANGE_SET_IMPORT.to_owned()),
+        rollback_configuration: options.rollback.clone(),
+        capabilities: Some(vec![
+            CAPABILITY_IAM.to_owned(),
+            CAPABILITY_NAMED_IAM.to_owned(),
+            CAPABILITY_AUTO_EXPAND.to_owned(),
+        ]),
+        resources_to_import: Some(
+            imports
+                .into_iter()
+                .map(|(logical_id, import)| ResourceToImport {
+                    logical_resource_id: logical_id,
+                    resource_type: import.resource_type,
+                    resource_identifier: import.identifier,
+                })
+                .collect(),
+        ),
+        ..Default::default()
+    };
+    match template {
+        Template::TemplateBody(body) => {
+            create_change_set_input.template_body = Some(body.to_owned())
+        }
+        Template::TemplateURL(url) => create_change_set_input.template_url = Some(url.to_owned()),
+    };
+    create_change_set_input
+}
+
+// Polls the change set until it can be executed; a failed change set is
+// cleaned up through `delete` before its reason is returned.
+fn wait_for_change_set(
+    describe: &dyn Fn() -> Result<DescribeChangeSetOutput, Error>,
+    delete: &dyn Fn() -> Result<(), Error>,
+) -> Result<(), Error> {
+    loop {
+        let change_set = describe()?;
+        match change_set.status.as_deref() {
+            Some(CHANGE_SET_CREATE_COMPLETE) => return Ok(()),
+            Some(CHANGE_SET_FAILED) => {
+                delete()?;
+                return Err(Error::CloudFormationError(format!(
+                    "import change set failed: {}",
+                    change_set.status_reason.unwrap_or_default()
+                )));
+            }
+            _ => sleep(Duration::from_secs(SLEEP_SECS)),
+        }
+    }
+}
+
+fn collect_failure_reasons(
+    stack_id: &String,
+    prefix: &str,
+    describe: &dyn Fn(&String, Option<String>) -> Result<(Vec<StackEvent>, Option<String>), Error>,
+) -> Result<Vec<String>, Error> {
+    let mut reasons = vec![];
+    let mut followed = vec![];
+    let mut after_rollback_start = false;
+    let mut next_token = None;
+    'pages: loop {
+        let (events, token) = describe(stack_id, next_token)?;
+        for event in events {
+            let status = event.resource_status.unwrap_or_default();
+            let logical_id = event.logical_resource_id.unwrap_or_default();
+            let own = event.physical_resource_id.as_ref() == Some(stack_id);
+            if own && OPERATION_START.contains(&status.as_str()) {
+                break 'pages;
+            }
+            if after_rollback_start && own && !status.ends_with(IN_PROGRESS_SUFFIX) {
+                break 'pages;
+            }
+            after_rollback_start = own && ROLLBACK_START.contains(&status.as_str());
+            if own || !status.ends_with(FAILED_SUFFIX) {
+                continue;
+            }
+            let path = format!("{}{}", prefix, logical_id);
+            match event.physical_resource_id {
+                Some(physical_id)
+                    if event.resource_type.as_deref() == Some(NESTED_STACK)
+                        && !followed.contains(&physical_id) =>
+                {
+                    followed.push(physical_id.to_owned());
+                    let nested =
+                        collect_failure_reasons(&physical_id, &format!("{}/", path), describe)?;
+                    if nested.is_empty() {
+                        reasons.push(format!(
+                            "{} {}: {}",
+                            path,
+                            status,
+                            event.resource_status_reason.unwrap_or_default()
+                        ));
+                    }
+                    reasons.extend(nested.into_iter().rev());
+                }
+                Some(_) if event.resource_type.as_deref() == Some(NESTED_STACK) => {}
+                _ => reasons.push(format!(
+                    "{} {}: {}",
+                    path,
+                    status,
+                    event.resource_status_reason.unwrap_or_default()
+                )),
+            }
+        }
+        next_token = token;
+        if next_token.is_none() {
+            break;
+        }
+    }
+    reasons.reverse();
+    Ok(reasons)
+}
+
+// The stack's drift status once detection has finished, `None` while it is
+// still in progress.
+fn drift_status(status: DescribeStackDriftDetectionStatusOutput) -> Result<Option<String>, Error> {
+    if status.detection_status == DETECTION_COMPLETE {
+        Ok(Some(status.stack_drift_status.unwrap_or_default()))
+    } else if status.detection_status == DETECTION_FAILED {
+        Err(Error::CloudFormationError(format!(
+            "drift detection failed: {}",
+            status.detection_status_reason.unwrap_or_default()
+        )))
+    } else {
+        Ok(None)
+    }
+}
+
+fn with_drift(output: Option<Output>, drift: Option<Drift>) -> Option<Output> {
+    match drift {
+        Some(drift) => {
+            let mut output = output.unwrap_or(Output {
+                outputs: HashMap::new(),
+                nested: HashMap::new(),
+                drift: None,
+            });
+            output.drift = Some(drift);
+            Some(output)
+        }
+        None => output,
+    }
+}
+
+// A stack whose creation failed cannot be updated, only deleted; with
+// `OnFailure::DoNothing` it is left in `CREATE_FAILED`.
+fn failed_create(status: &str) -> bool {
+    status == CREATE_FAILED || status == ROLLBACK_COMPLETE || status == ROLLBACK_FAILED
+}
+
+fn same_policy(current: Option<String>, policy: &String) -> bool {
+    current.map_or(false, |current| {
+        match (
+            serde_json::from_str::<serde_json::Value>(&current),
+            serde_json::from_str::<serde_json::Value>(policy),
+        ) {
+            (Ok(current), Ok(policy)) => current == policy,
+            _ => &current == policy,
+        }
+    })
+}
+
+fn non_empty(s: &String) -> Option<String> {
+    if s == "" {
+        None
+    } else {
+        Some(s.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_on_failure() {
+        assert_eq!(OnFailure::default(), OnFailure::Rollback);
+        assert_eq!(OnFailure::Rollback.as_str(), "ROLLBACK");
+        assert_eq!(OnFailure::Delete.as_str(), "DELETE");
+        assert_eq!(OnFailure::DoNothing.as_str(), "DO_NOTHING");
+
+        assert!(failed_create(CREATE_FAILED));
+        assert!(failed_create(ROLLBACK_COMPLETE));
+        assert!(!failed_create(CREATE_COMPLETE));
+        assert!(!failed_create(UPDATE_ROLLBACK_COMPLETE));
+    }
+
+    #[test]
+    fn test_options() {
+        let context = crate::ferro::Context::default();
+        let options = CloudFormation::default().options(&context).unwrap();
+        assert_eq!(options.stack_policy, "");
+        assert!(options.rollback.is_none());
+
+        let cfn = CloudFormation {
+            stack_policy: Box::new(crate::lazy::string("{}".to_owned())),
+            rollback_alarms: Box::new(|_| Ok(vec!["arn:aws:cloudwatch:alarm:errors".to_owned()])),
+            rollback_monitoring_minutes: Some(10),
+            ..Default::default()
+        };
+        let options = cfn.options(&context).unwrap();
+        assert_eq!(options.stack_policy, "{}");
+        let rollback = options.rollback.unwrap();
+        assert_eq!(rollback.monitoring_time_in_minutes, Some(10));
+        let triggers = rollback.rollback_triggers.unwrap();
+        assert_eq!(triggers.len(), 1);
+        assert_eq!(triggers[0].arn, "arn:aws:cloudwatch:alarm:errors");
+        assert_eq!(triggers[0].type_, CLOUDWATCH_ALARM);
+    }
+
+    #[test]
+    fn test_sync_stack_policy() {
+        // An empty policy leaves the stack alone without calling AWS.
+        let cfn = CloudFormation::default();
+        assert!(!cfn
+            .sync_stack_policy(&"web".to_owned(), &"".to_owned())
+            .unwrap());
+
+        let policy = r#"{"Statement": [{"Effect": "Allow", "Action": "Update:*"}]}"#.to_owned();
+        let current = r#"{"Statement":[{"Action":"Update:*","Effect":"Allow"}]}"#.to_owned();
+        assert!(same_policy(Some(current), &policy));
+        assert!(!same_policy(None, &policy));
+        assert!(!same_policy(Some("{}".to_owned()), &policy));
+        assert!(same_policy(
+            Some("not json".to_owned()),
+            &"not json".to_owned()
+        ));
+    }
+
+    fn import(resource_type: &str, key: &str, value: &str) -> Import {
+        let mut identifier = HashMap::new();
+        identifier.insert(key.to_owned(), value.to_owned());
+        Import {
+            resource_type: resource_type.to_owned(),
+            identifier: identifier,
+        }
+    }
+
+    #[test]
+    fn test_collect_pending_imports() {
+        let mut imports = HashMap::new();
+        imports.insert(
+            "Bucket".to_owned(),
+            import("AWS::S3::Bucket", "BucketName", "web"),
+        );
+        imports.insert(
+            "Queue".to_owned(),
+            import("AWS::SQS::Queue", "QueueUrl", "jobs"),
+        );
+        imports.insert(
+            "Table".to_owned(),
+            import("AWS::DynamoDB::Table", "TableName", "t"),
+        );
+
+        let list = |next_token: Option<String>| match next_token.as_deref() {
+            None => Ok((
+                vec!["Bucket".to_owned(), "Role".to_owned()],
+                Some("2".to_owned()),
+            )),
+            Some("2") => Ok((vec!["Queue".to_owned()], None)),
+            Some(token) => panic!("unexpected page {}", token),
+        };
+        let pending = collect_pending_imports(imports.clone(), &list).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending["Table"].identifier["TableName"], "t");
+
+        // Nothing to import means the stack is not listed at all.
+        let list = |_: Option<String>| -> Result<(Vec<String>, Option<String>), Error> {
+            panic!("listed resources without imports")
+        };
+        assert!(collect_pending_imports(HashMap::new(), &list)
+            .unwrap()
+            .is_empty());
+
+        let list = |_: Option<String>| Err(Error::CloudFormationError("denied".to_owned()));
+        assert!(collect_pending_imports(imports, &list).is_err());
+    }
+
+    #[test]
+    fn test_import_change_set_input() {
+        let mut imports = HashMap::new();
+        imports.insert(
+            "Bucket".to_owned(),
+            import("AWS::S3::Bucket", "BucketName", "web"),
+        );
+        let options = StackOptions {
+            stack_policy: "".to_owned(),
+            stack_policy_during_update: "".to_owned(),
+            rollback: Some(RollbackConfiguration {
+                monitoring_time_in_minutes: Some(5),
+                rollback_triggers: None,
+            }),
+        };
+        let input = import_change_set_input(
+            &"web".to_owned(),
+            &"ferro-import-1".to_owned(),
+            &Template::TemplateURL("https://example.com/web.yml".to_owned()),
+            imports,
+            &options,
+        );
+        assert_eq!(input.stack_name, "web");
+        assert_eq!(input.change_set_name, "ferro-import-1");
+        assert_eq!(input.change_set_type, Some(CHANGE_SET_IMPORT.to_owned()));
+        assert_eq!(
+            input.template_url,
+            Some("https://example.com/web.yml".to_owned())
+        );
+        assert!(input.template_body.is_none());
+        assert_eq!(
+            input
+                .rollback_configuration
+                .unwrap()
+                .monitoring_time_in_minutes,
+            Some(5)
+        );
+        assert_eq!(input.capabilities.unwrap().len(), 3);
+        let resources = input.resources_to_import.unwrap();
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0].logical_resource_id, "Bucket");
+        assert_eq!(resources[0].resource_type, "AWS::S3::Bucket");
+        assert_eq!(resources[0].resource_identifier["BucketName"], "web");
+
+        let input = import_change_set_input(
+            &"web".to_owned(),
+            &"ferro-import-1".to_owned(),
+            &Template::TemplateBody("Resources: {}".to_owned()),
+            HashMap::new(),
+            &options,
+        );
+        assert_eq!(input.template_body, Some("Resources: {}".to_owned()));
+        assert!(input.template_url.is_none());
+    }
+
+    #[test]
+    fn test_wait_for_change_set() {
+        let deleted = std::cell::Cell::new(0);
+        let delete = || {
+            deleted.set(deleted.get() + 1);
+            Ok(())
+        };
+        let change_set = |status: &str| DescribeChangeSetOutput {
+            status: Some(status.to_owned()),
+            status_reason: Some("resource not found".to_owned()),
+            ..Default::default()
+        };
+
+        let describe = || Ok(change_set(CHANGE_SET_CREATE_COMPLETE));
+        assert!(wait_for_change_set(&describe, &delete).is_ok());
+        assert_eq!(deleted.get(), 0);
+
+        let describe = || Ok(change_set(CHANGE_SET_FAILED));
+        match wait_for_change_set(&describe, &delete) {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "import change set failed: resource not found"
+            ),
+            Ok(()) => panic!("expected failed change set"),
+        }
+        assert_eq!(deleted.get(), 1);
+
+        // A failed deletion is reported instead of the change set's reason.
+        let delete = || Err(Error::CloudFormationError("delete denied".to_owned()));
+        match wait_for_change_set(&describe, &delete) {
+            Err(e) => assert_eq!(e.to_string(), "delete denied"),
+            Ok(()) => panic!("expected failed deletion"),
+        }
+    }
+
+    fn event(
+        logical_id: &str,
+        physical_id: &str,
+        resource_type: &str,
+        status: &str,
+        reason: &str,
+    ) -> StackEvent {
+        StackEvent {
+            logical_resource_id: Some(logical_id.to_owned()),
+            physical_resource_id: Some(physical_id.to_owned()),
+            resource_type: Some(resource_type.to_owned()),
+            resource_status: Some(status.to_owned()),
+            resource_status_reason: Some(reason.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_collect_failure_reasons() {
+        let describe = |stack_id: &String, next_token: Option<String>| {
+            let events = match (stack_id.as_str(), next_token.as_deref()) {
+                ("root", None) => (
+                    vec![
+                        event("root", "root", NESTED_STACK, "UPDATE_ROLLBACK_COMPLETE", ""),
+                        event("Bucket", "bucket", "AWS::S3::Bucket", "UPDATE_COMPLETE", ""),
+                        event(
+                            "root",
+                            "root",
+                            NESTED_STACK,
+                            "UPDATE_ROLLBACK_IN_PROGRESS",
+                            "",
+                        ),
+                        event(
+                            "Network",
+                            "network",
+                            NESTED_STACK,
+                            "UPDATE_FAILED",
+                            "Embedded stack failed",
+                        ),
+                    ],
+                    Some("2".to_owned()),
+                ),
+                ("root", Some("2")) => (
+                    vec![
+                        event(
+                            "Queue",
+                            "queue",
+                            "AWS::SQS::Queue",
+                            "UPDATE_FAILED",
+                            "cancelled",
+                        ),
+                        event("root", "root", NESTED_STACK, "UPDATE_IN_PROGRESS", ""),
+                        event("Old", "old", "AWS::SQS::Queue", "CREATE_FAILED", "stale"),
+                    ],
+                    Some("3".to_owned()),
+                ),
+                ("network", None) => (
+                    vec![
+                        event(
+                            "network",
+                            "network",
+                            NESTED_STACK,
+                            "UPDATE_ROLLBACK_COMPLETE",
+                            "",
+                        ),
+                        event(
+                            "Subnet",
+                            "subnet",
+                            "AWS::EC2::Subnet",
+                            "UPDATE_FAILED",
+                            "CIDR conflict",
+                        ),
+                        event("network", "network", NESTED_STACK, "UPDATE_IN_PROGRESS", ""),
+                    ],
+                    None,
+                ),
+                // Events before the operation started are never fetched.
+                _ => panic!("unexpected page {} {:?}", stack_id, next_token),
+            };
+            Ok(events)
+        };
+        let reasons = collect_failure_reasons(&"root".to_owned(), "", &describe).unwrap();
+        assert_eq!(
+            reasons,
+            vec![
+                "Queue UPDATE_FAILED: cancelled",
+                "Network/Subnet UPDATE_FAILED: CIDR conflict",
+            ]
+        );
+
+        // A nested stack whose own events show no failure is reported itself.
+        let describe = |stack_id: &String, _: Option<String>| {
+            let events = match stack_id.as_str() {
+                "root" => vec![
+                    event(
+                        "Network",
+                        "network",
+                        NESTED_STACK,
+                        "CREATE_FAILED",
+                        "Embedded stack failed",
+                    ),
+                    event("root", "root", NESTED_STACK, "CREATE_IN_PROGRESS", ""),
+                ],
+                _ => vec![],
+            };
+            Ok((events, None))
+        };
+        let reasons = collect_failure_reasons(&"root".to_owned(), "", &describe).unwrap();
+        assert_eq!(
+            reasons,
+            vec!["Network CREATE_FAILED: Embedded stack failed"]
+        );
+
+        let describe =
+            |_: &String, _: Option<String>| Err(Error::CloudFormationError("throttled".to_owned()));
+        assert!(collect_failure_reasons(&"root".to_owned(), "", &describe).is_err());
+    }
+
+    #[test]
+    fn test_drifted_resource() {
+        let resource = DriftedResource::from(StackResourceDrift {
+            logical_resource_id: "Bucket".to_owned(),
+            physical_resource_id: Some("web-bucket".to_owned()),
+            resource_type: "AWS::S3::Bucket".to_owned(),
+            stack_resource_drift_status: DRIFT_MODIFIED.to_owned(),
+            property_differences: Some(vec![rusoto_cloudformation::PropertyDifference {
+                property_path: "/VersioningConfiguration/Status".to_owned(),
+                expected_value: "Enabled".to_owned(),
+                actual_value: "Suspended".to_owned(),
+                difference_type: "NOT_EQUAL".to_owned(),
+            }]),
+            ..Default::default()
+        });
+        assert_eq!(resource.logical_resource_id, "Bucket");
+        assert_eq!(resource.physical_resource_id, Some("web-bucket".to_owned()));
+        assert_eq!(resource.status, DRIFT_MODIFIED);
+        assert_eq!(resource.differences.len(), 1);
+        assert_eq!(
+            resource.differences[0].property_path,
+            "/VersioningConfiguration/Status"
+        );
+        assert_eq!(resource.differences[0].actual_value, "Suspended");
+
+        let resource = DriftedResource::from(StackResourceDrift {
+            logical_resource_id: "Queue".to_owned(),
+            stack_resource_drift_status: DRIFT_DELETED.to_owned(),
+            ..Default::default()
+        });
+        assert!(resource.physical_resource_id.is_none());
+        assert!(resource.differences.is_empty());
+    }
+
+    #[test]
+    fn test_drift_status() {
+        let status =
+            |detection: &str, drift: Option<&str>| DescribeStackDriftDetectionStatusOutput {
+                detection_status: detection.to_owned(),
+                detection_status_reason: Some("access denied".to_owned()),
+                stack_drift_status: drift.map(|d| d.to_owned()),
+                ..Default::default()
+            };
+        assert_eq!(
+            drift_status(status("DETECTION_IN_PROGRESS", None)).unwrap(),
+            None
+        );
+        assert_eq!(
+            drift_status(status(DETECTION_COMPLETE, Some(DRIFTED))).unwrap(),
+            Some(DRIFTED.to_owned())
+        );
+        assert_eq!(
+            drift_status(status(DETECTION_COMPLETE, Some("IN_SYNC"))).unwrap(),
+            Some("IN_SYNC".to_owned())
+        );
+        match drift_status(status(DETECTION_FAILED, None)) {
+            Err(e) => assert_eq!(e.to_string(), "drift detection failed: access denied"),
+            Ok(status) => panic!("expected failure, got {:?}", status),
+        }
+    }
+
+    #[test]
+    fn test_with_drift() {
+        let drift = |status: &str| Drift {
+            status: status.to_owned(),
+            resources: vec![],
+        };
+        assert!(drift(DRIFTED).drifted());
+        assert!(!drift("IN_SYNC").drifted());
+
+        assert!(with_drift(None, None).is_none());
+        let output = with_drift(None, Some(drift(DRIFTED))).unwrap();
+        assert!(output.outputs.is_empty());
+        assert!(output.drift.unwrap().drifted());
+
+        let mut outputs = HashMap::new();
+        outputs.insert("Url".to_owned(), "https://example.com".to_owned());
+        let output = Output {
+            outputs: outputs,
+            nested: HashMap::new(),
+            drift: None,
+        };
+        let output = with_drift(Some(output), Some(drift("IN_SYNC"))).unwrap();
+        assert_eq!(output.outputs["Url"], "https://example.com");
+        assert!(!output.drift.unwrap().drifted());
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::default::Default;
+
+use rusoto_cloudformation::{
+    CloudFormation as CF, CloudFormationClient, DescribeStacksInput, ListExportsInput, Stack,
+};
+use serde::Serialize;
+
+use super::cloudformation::Error;
+
+const CLOUDFORMATION_INFO: &str = "cloudformation_info";
+
+#[derive(Debug, Default, Serialize)]
+pub struct Output {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stack_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stack_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stack_status: Option<String>,
+    outputs: HashMap<String, String>,
+    parameters: HashMap<String, String>,
+    tags: HashMap<String, String>,
+    exports: HashMap<String, String>,
+}
+
+#[typetag::serialize]
+impl crate::ferro::Output for Output {
+    fn to_value(&self) -> Result<serde_json::value::Value, serde_json::error::Error> {
+        serde_json::to_value(self)
+    }
+}
+
+pub struct CloudFormationInfo {
+    pub stack_name: Box<crate::lazy::String>,
+    pub exports: Box<crate::lazy::Vec<String>>,
+    pub cfn: CloudFormationClient,
+}
+
+impl Default for CloudFormationInfo {
+    fn default() -> Self {
+        CloudFormationInfo {
+            stack_name: Box::new(crate::lazy::string("".to_owned())),
+            exports: Box::new(|_| Ok(vec![])),
+            cfn: CloudFormationClient::new(crate::lookup::aws::region()),
+        }
+    }
+}
+
+impl CloudFormationInfo {
+    fn describe_stack(&self, stack_name: &str) -> Result<Stack, Error> {
+        let result = self
+            .cfn
+            .describe_stacks(DescribeStacksInput {
+                next_token: None,
+                stack_name: Some(stack_name.to_owned()),
+            })
+            .sync()?;
+        result
+            .stacks
+            .and_then(|stacks| stacks.into_iter().next())
+            .ok_or(Error::StackNotFoundError)
+    }
+
+    fn list_exports(&self, names: &[String]) -> Result<HashMap<String, String>, Error> {
+        let mut exports = HashMap::new();
+        let mut next_token = None;
+        loop {
+            let result = self
+                .cfn
+                .list_exports(ListExportsInput {
+                    next_token: next_token,
+                })
+                .sync()
+                .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+            for export in result.exports.unwrap_or_default() {
+                if let (Some(name), Some(value)) = (export.name, export.value) {
+                    if names.contains(&name) {
+                        exports.insert(name, value);
+                    }
+                }
+            }
+            next_token = result.next_token;
+            if next_token.is_none() || exports.len() == names.len() {
+                break;
+            }
+        }
+
+        let missing: Vec<&str> = names
+            .iter()
+            .filter(|n| !exports.contains_key(*n))
+            .map(|n| n.as_str())
+            .collect();
+        if !missing.is_empty() {
+            return Err(Error::CloudFormationError(format!(
+                "exports not found: {}",
+                missing.join(", ")
+            )));
+        }
+        Ok(exports)
+    }
+}
+
+impl crate::ferro::Module for CloudFormationInfo {
+    fn name(&self) -> String {
+        CLOUDFORMATION_INFO.to_owned()
+    }
+
+    fn apply(
+        &self,
+        context: &crate::ferro::Context,
+    ) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        let stack_name = (self.stack_name)(context)?;
+        let export_names = (self.exports)(context)?;
+        if stack_name == "" && export_names.is_empty() {
+            return crate::ferro::result_error(
+                false,
+                "stack_name or exports is required".to_owned(),
+            );
+        }
+
+        let mut output = Output::default();
+        if stack_name != "" {
+            output = match self.describe_stack(&stack_name) {
+                Ok(stack) => stack_output(stack),
+                Err(Error::StackNotFoundError) => {
+                    return crate::ferro::result_error(
+                        false,
+                        format!("stack {} does not exist", stack_name),
+                    )
+                }
+                Err(e) => return crate::ferro::result_error(false, e.to_string()),
+            };
+        }
+        if !export_names.is_empty() {
+            match self.list_exports(&export_names) {
+                Ok(exports) => output.exports.extend(exports),
+                Err(e) => return crate::ferro::result_error(false, e.to_string()),
+            }
+        }
+
+        crate::ferro::result_response(false, Some(Box::new(output)))
+    }
+
+    fn destroy(&self) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        crate::ferro::result_response(false, None)
+    }
+}
+
+fn stack_output(stack: Stack) -> Output {
+    let mut output = Output::default();
+    for stack_output in stack.outputs.unwrap_or_default() {
+        if let (Some(key), Some(value)) = (stack_output.output_key, stack_output.output_value) {
+            if let Some(export_name) = stack_output.export_name {
+                output.exports.insert(export_name, value.clone());
+            }
+            output.outputs.insert(key, value);
+        }
+    }
+    for parameter in stack.parameters.unwrap_or_default() {
+        if let (Some(key), Some(value)) = (parameter.parameter_key, parameter.parameter_value) {
+            output.parameters.insert(key, value);
+        }
+    }
+    for tag in stack.tags.unwrap_or_default() {
+        output.tags.insert(tag.key, tag.value);
+    }
+    output.stack_name = Some(stack.stack_name);
+    output.stack_id = stack.stack_id;
+    output.stack_status = Some(stack.stack_status);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusoto_cloudformation::{Parameter, Tag};
+
+    #[test]
+    fn test_stack_output() {
+        let stack = Stack {
+            stack_name: "web".to_owned(),
+            stack_id: Some("arn:aws:cloudformation:us-east-1:123:stack/web/1".to_owned()),
+            stack_status: "UPDATE_COMPLETE".to_owned(),
+            outputs: Some(vec![
+                rusoto_cloudformation::Output {
+                    output_key: Some("VpcId".to_owned()),
+                    output_value: Some("vpc-1".to_owned()),
+                    export_name: Some("web-vpc".to_owned()),
+                    ..Default::default()
+                },
+                rusoto_cloudformation::Output {
+                    output_key: Some("Url".to_owned()),
+                    output_value: Some("https://web".to_owned()),
+                    ..Default::default()
+                },
+                rusoto_cloudformation::Output {
+                    output_key: Some("Empty".to_owned()),
+                    ..Default::default()
+                },
+            ]),
+            parameters: Some(vec![Parameter {
+                parameter_key: Some("Env".to_owned()),
+                parameter_value: Some("prod".to_owned()),
+                ..Default::default()
+            }]),
+            tags: Some(vec![Tag {
+                key: "team".to_owned(),
+                value: "platform".to_owned(),
+            }]),
+            ..Default::default()
+        };
+
+        let output = stack_output(stack);
+        assert_eq!(output.stack_name, Some("web".to_owned()));
+        assert_eq!(output.stack_status, Some("UPDATE_COMPLETE".to_owned()));
+        assert_eq!(output.outputs.len(), 2);
+        assert_eq!(output.outputs["VpcId"], "vpc-1");
+        assert_eq!(output.outputs["Url"], "https://web");
+        assert_eq!(output.exports.len(), 1);
+        assert_eq!(output.exports["web-vpc"], "vpc-1");
+        assert_eq!(output.parameters["Env"], "prod");
+        assert_eq!(output.tags["team"], "platform");
+    }
+}
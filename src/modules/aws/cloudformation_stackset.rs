@@ -0,0 +1,724 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::From;
+use std::default::Default;
+use std::thread::sleep;
+use std::time::Duration;
+
+use rusoto_cloudformation::{
+    CloudFormation as CF, CloudFormationClient, CreateStackInstancesInput, CreateStackSetInput,
+    DeleteStackInstancesInput, DeploymentTargets, DescribeStackSetError, DescribeStackSetInput,
+    DescribeStackSetOperationInput, ListStackInstancesInput, Parameter, StackInstanceSummary,
+    StackSet, StackSetOperationPreferences, UpdateStackInstancesInput, UpdateStackSetInput,
+};
+use rusoto_core::RusotoError;
+use serde::Serialize;
+
+use super::cloudformation::{Error, Template};
+
+const CLOUDFORMATION_STACKSET: &str = "cloudformation_stackset";
+
+const CAPABILITY_IAM: &str = "CAPABILITY_IAM";
+const CAPABILITY_NAMED_IAM: &str = "CAPABILITY_NAMED_IAM";
+const CAPABILITY_AUTO_EXPAND: &str = "CAPABILITY_AUTO_EXPAND";
+
+const SELF_MANAGED: &str = "SELF_MANAGED";
+const SERVICE_MANAGED: &str = "SERVICE_MANAGED";
+
+const OPERATION_SUCCEEDED: &str = "SUCCEEDED";
+const OPERATION_FAILED: &str = "FAILED";
+const OPERATION_STOPPED: &str = "STOPPED";
+
+const INSTANCE_OUTDATED: &str = "OUTDATED";
+const INSTANCE_INOPERABLE: &str = "INOPERABLE";
+
+const SLEEP_SECS: u64 = 5;
+
+impl From<RusotoError<DescribeStackSetError>> for Error {
+    fn from(e: RusotoError<DescribeStackSetError>) -> Self {
+        match e {
+            RusotoError::Service(DescribeStackSetError::StackSetNotFound(_)) => {
+                Error::StackNotFoundError
+            }
+            _ => Error::CloudFormationError(e.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OperationPreferences {
+    pub failure_tolerance_count: Option<i64>,
+    pub failure_tolerance_percentage: Option<i64>,
+    pub max_concurrent_count: Option<i64>,
+    pub max_concurrent_percentage: Option<i64>,
+}
+
+impl OperationPreferences {
+    fn to_input(&self) -> StackSetOperationPreferences {
+        StackSetOperationPreferences {
+            failure_tolerance_count: self.failure_tolerance_count,
+            failure_tolerance_percentage: self.failure_tolerance_percentage,
+            max_concurrent_count: self.max_concurrent_count,
+            max_concurrent_percentage: self.max_concurrent_percentage,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Instance {
+    account: Option<String>,
+    region: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    organizational_unit_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stack_id: Option<String>,
+    status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status_reason: Option<String>,
+}
+
+impl From<StackInstanceSummary> for Instance {
+    fn from(summary: StackInstanceSummary) -> Self {
+        Instance {
+            account: summary.account,
+            region: summary.region,
+            organizational_unit_id: summary.organizational_unit_id,
+            stack_id: summary.stack_id,
+            status: summary.status,
+            status_reason: summary.status_reason,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Output {
+    stack_set_id: Option<String>,
+    operations: Vec<String>,
+    instances: Vec<Instance>,
+}
+
+#[typetag::serialize]
+impl crate::ferro::Output for Output {
+    fn to_value(&self) -> Result<serde_json::value::Value, serde_json::error::Error> {
+        serde_json::to_value(self)
+    }
+}
+
+pub struct CloudFormationStackSet {
+    pub stack_set_name: Box<crate::lazy::String>,
+    pub template: Box<dyn Fn(&crate::ferro::Context) -> Template + Send + Sync>,
+    pub accounts: Box<crate::lazy::Vec<String>>,
+    pub organizational_units: Box<crate::lazy::Vec<String>>,
+    pub regions: Box<crate::lazy::Vec<String>>,
+    pub parameters: Box<dyn Fn(&crate::ferro::Context) -> HashMap<String, String> + Send + Sync>,
+    pub preferences: OperationPreferences,
+    pub cfn: CloudFormationClient,
+}
+
+impl Default for CloudFormationStackSet {
+    fn default() -> Self {
+        CloudFormationStackSet {
+            stack_set_name: Box::new(crate::lazy::string("".to_owned())),
+            template: Box::new(|_| Template::TemplateBody("".to_owned())),
+            accounts: Box::new(|_| Ok(vec![])),
+            organizational_units: Box::new(|_| Ok(vec![])),
+            regions: Box::new(|_| Ok(vec![])),
+            parameters: Box::new(|_| HashMap::new()),
+            preferences: Default::default(),
+            cfn: CloudFormationClient::new(crate::lookup::aws::region()),
+        }
+    }
+}
+
+impl CloudFormationStackSet {
+    fn get_stack_set_info(&self, stack_set_name: &String) -> Result<StackSet, Error> {
+        let result = self
+            .cfn
+            .describe_stack_set(DescribeStackSetInput {
+                stack_set_name: stack_set_name.to_owned(),
+                ..Default::default()
+            })
+            .sync()?;
+        result.stack_set.ok_or(Error::StackNotFoundError)
+    }
+
+    fn create_stack_set(
+        &self,
+        stack_set_name: &String,
+        template: &Template,
+        parameters: &HashMap<String, String>,
+        service_managed: bool,
+    ) -> Result<Option<String>, Error> {
+        let mut input = CreateStackSetInput {
+            stack_set_name: stack_set_name.to_owned(),
+            capabilities: Some(capabilities()),
+            parameters: Some(to_parameters(parameters)),
+            permission_model: Some(
+                if service_managed {
+                    SERVICE_MANAGED
+                } else {
+                    SELF_MANAGED
+                }
+                .to_owned(),
+            ),
+            ..Default::default()
+        };
+        if service_managed {
+            input.auto_deployment = Some(rusoto_cloudformation::AutoDeployment {
+                enabled: Some(true),
+                retain_stacks_on_account_removal: Some(false),
+            });
+        }
+        match template {
+            Template::TemplateBody(body) => input.template_body = Some(body.to_owned()),
+            Template::TemplateURL(url) => input.template_url = Some(url.to_owned()),
+        };
+
+        let result = self
+            .cfn
+            .create_stack_set(input)
+            .sync()
+            .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+        Ok(result.stack_set_id)
+    }
+
+    fn update_stack_set(
+        &self,
+        stack_set_name: &String,
+        template: &Template,
+        parameters: &HashMap<String, String>,
+    ) -> Result<Option<String>, Error> {
+        let mut input = UpdateStackSetInput {
+            stack_set_name: stack_set_name.to_owned(),
+            capabilities: Some(capabilities()),
+            parameters: Some(to_parameters(parameters)),
+            operation_preferences: Some(self.preferences.to_input()),
+            ..Default::default()
+        };
+        match template {
+            Template::TemplateBody(body) => input.template_body = Some(body.to_owned()),
+            Template::TemplateURL(url) => input.template_url = Some(url.to_owned()),
+        };
+
+        let result = self
+            .cfn
+            .update_stack_set(input)
+            .sync()
+            .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+        Ok(result.operation_id)
+    }
+
+    fn create_stack_instances(
+        &self,
+        stack_set_name: &String,
+        targets: Vec<String>,
+        regions: Vec<String>,
+        service_managed: bool,
+    ) -> Result<Option<String>, Error> {
+        let (accounts, deployment_targets) = deployment(targets, service_managed);
+        let result = self
+            .cfn
+            .create_stack_instances(CreateStackInstancesInput {
+                stack_set_name: stack_set_name.to_owned(),
+                accounts: accounts,
+                deployment_targets: deployment_targets,
+                regions: regions,
+                operation_preferences: Some(self.preferences.to_input()),
+                ..Default::default()
+            })
+            .sync()
+            .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+        Ok(result.operation_id)
+    }
+
+    fn update_stack_instances(
+        &self,
+        stack_set_name: &String,
+        targets: Vec<String>,
+        regions: Vec<String>,
+        service_managed: bool,
+    ) -> Result<Option<String>, Error> {
+        let (accounts, deployment_targets) = deployment(targets, service_managed);
+        let result = self
+            .cfn
+            .update_stack_instances(UpdateStackInstancesInput {
+                stack_set_name: stack_set_name.to_owned(),
+                accounts: accounts,
+                deployment_targets: deployment_targets,
+                regions: regions,
+                operation_preferences: Some(self.preferences.to_input()),
+                ..Default::default()
+            })
+            .sync()
+            .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+        Ok(result.operation_id)
+    }
+
+    fn delete_stack_instances(
+        &self,
+        stack_set_name: &String,
+        targets: Vec<String>,
+        regions: Vec<String>,
+        service_managed: bool,
+    ) -> Result<Option<String>, Error> {
+        let (accounts, deployment_targets) = deployment(targets, service_managed);
+        let result = self
+            .cfn
+            .delete_stack_instances(DeleteStackInstancesInput {
+                stack_set_name: stack_set_name.to_owned(),
+                accounts: accounts,
+                deployment_targets: deployment_targets,
+                regions: regions,
+                retain_stacks: false,
+                operation_preferences: Some(self.preferences.to_input()),
+                ..Default::default()
+            })
+            .sync()
+            .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+        Ok(result.operation_id)
+    }
+
+    fn list_stack_instances(
+        &self,
+        stack_set_name: &String,
+    ) -> Result<Vec<StackInstanceSummary>, Error> {
+        let mut instances = vec![];
+        let mut next_token = None;
+        loop {
+            let result = self
+                .cfn
+                .list_stack_instances(ListStackInstancesInput {
+                    stack_set_name: stack_set_name.to_owned(),
+                    next_token: next_token,
+                    ..Default::default()
+                })
+                .sync()
+                .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+            instances.extend(result.summaries.unwrap_or_default());
+            next_token = result.next_token;
+            if next_token.is_none() {
+                return Ok(instances);
+            }
+        }
+    }
+
+    fn wait_for_operation(
+        &self,
+        stack_set_name: &String,
+        operation_id: &String,
+    ) -> Result<(), Error> {
+        loop {
+            let result = self
+                .cfn
+                .describe_stack_set_operation(DescribeStackSetOperationInput {
+                    stack_set_name: stack_set_name.to_owned(),
+                    operation_id: operation_id.to_owned(),
+                    ..Default::default()
+                })
+                .sync()
+                .map_err(|e| Error::CloudFormationError(e.to_string()))?;
+            let status = result
+                .stack_set_operation
+                .and_then(|operation| operation.status)
+                .ok_or(Error::UnknownError)?;
+            if status == OPERATION_SUCCEEDED {
+                return Ok(());
+            } else if status == OPERATION_FAILED || status == OPERATION_STOPPED {
+                return Err(Error::CloudFormationError(format!(
+                    "operation {} {}",
+                    operation_id, status
+                )));
+            } else {
+                sleep(Duration::from_secs(SLEEP_SECS));
+            }
+        }
+    }
+
+    fn apply_stack_set(
+        &self,
+        stack_set_name: &String,
+        template: &Template,
+        parameters: &HashMap<String, String>,
+        targets: &[String],
+        regions: &[String],
+        service_managed: bool,
+    ) -> Result<(bool, Output), Error> {
+        let mut changed = false;
+        let mut operations = vec![];
+        let stack_set_id = match self.get_stack_set_info(stack_set_name) {
+            Ok(stack_set) => {
+                let unchanged = match template {
+                    Template::TemplateBody(body) => stack_set.template_body.as_ref() == Some(body),
+                    Template::TemplateURL(_) => false,
+                } && same_parameters(&stack_set.parameters, parameters);
+                if !unchanged {
+                    if let Some(operation_id) =
+                        self.update_stack_set(stack_set_name, template, parameters)?
+                    {
+                        self.wait_for_operation(stack_set_name, &operation_id)?;
+                        operations.push(operation_id);
+                    }
+                    changed = true;
+                }
+                stack_set.stack_set_id
+            }
+            Err(Error::StackNotFoundError) => {
+                changed = true;
+                self.create_stack_set(stack_set_name, template, parameters, service_managed)?
+            }
+            Err(e) => return Err(e),
+        };
+
+        let existing = self.list_stack_instances(stack_set_name)?;
+        let inoperable = selected(
+            deployed(&existing, service_managed, Some(INSTANCE_INOPERABLE)),
+            targets,
+            regions,
+        );
+        if !inoperable.is_empty() {
+            let pairs: Vec<String> = inoperable
+                .iter()
+                .map(|(target, region)| format!("{}/{}", target, region))
+                .collect();
+            return Err(Error::CloudFormationError(format!(
+                "stack instances are {} and must be cleaned up manually: {}",
+                INSTANCE_INOPERABLE,
+                pairs.join(", ")
+            )));
+        }
+
+        for (group_targets, group_regions) in stale(&existing, targets, regions, service_managed) {
+            if let Some(operation_id) = self.delete_stack_instances(
+                stack_set_name,
+                group_targets,
+                group_regions,
+                service_managed,
+            )? {
+                self.wait_for_operation(stack_set_name, &operation_id)?;
+                operations.push(operation_id);
+            }
+            changed = true;
+        }
+        for (group_targets, group_regions) in missing(&existing, targets, regions, service_managed)
+        {
+            if let Some(operation_id) = self.create_stack_instances(
+                stack_set_name,
+                group_targets,
+                group_regions,
+                service_managed,
+            )? {
+                self.wait_for_operation(stack_set_name, &operation_id)?;
+                operations.push(operation_id);
+            }
+            changed = true;
+        }
+        for (group_targets, group_regions) in outdated(&existing, targets, regions, service_managed)
+        {
+            if let Some(operation_id) = self.update_stack_instances(
+                stack_set_name,
+                group_targets,
+                group_regions,
+                service_managed,
+            )? {
+                self.wait_for_operation(stack_set_name, &operation_id)?;
+                operations.push(operation_id);
+            }
+            changed = true;
+        }
+
+        let instances = if changed {
+            self.list_stack_instances(stack_set_name)?
+        } else {
+            existing
+        };
+        Ok((
+            changed,
+            Output {
+                stack_set_id: stack_set_id,
+                operations: operations,
+                instances: instances.into_iter().map(Instance::from).collect(),
+            },
+        ))
+    }
+}
+
+impl crate::ferro::Module for CloudFormationStackSet {
+    fn name(&self) -> String {
+        CLOUDFORMATION_STACKSET.to_owned()
+    }
+
+    fn apply(
+        &self,
+        context: &crate::ferro::Context,
+    ) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        let stack_set_name = (self.stack_set_name)(context)?;
+        let template = (self.template)(context);
+        let accounts = (self.accounts)(context)?;
+        let organizational_units = (self.organizational_units)(context)?;
+        let regions = (self.regions)(context)?;
+        let parameters = (self.parameters)(context);
+
+        if !accounts.is_empty() && !organizational_units.is_empty() {
+            return crate::ferro::result_error(
+                false,
+                "accounts and organizational_units are mutually exclusive".to_owned(),
+            );
+        }
+        let service_managed = !organizational_units.is_empty();
+        let targets = if service_managed {
+            organizational_units
+        } else {
+            accounts
+        };
+
+        match self.apply_stack_set(
+            &stack_set_name,
+            &template,
+            &parameters,
+            &targets,
+            &regions,
+            service_managed,
+        ) {
+            Ok((changed, output)) => crate::ferro::result_response(changed, Some(Box::new(output))),
+            Err(e) => crate::ferro::result_error(true, e.to_string()),
+        }
+    }
+
+    fn destroy(&self) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        crate::ferro::result_response(false, None)
+    }
+}
+
+fn capabilities() -> Vec<String> {
+    vec![
+        CAPABILITY_IAM.to_owned(),
+        CAPABILITY_NAMED_IAM.to_owned(),
+        CAPABILITY_AUTO_EXPAND.to_owned(),
+    ]
+}
+
+fn to_parameters(parameters: &HashMap<String, String>) -> Vec<Parameter> {
+    let sorted: BTreeMap<&String, &String> = parameters.iter().collect();
+    sorted
+        .into_iter()
+        .map(|(key, value)| Parameter {
+            parameter_key: Some(key.to_owned()),
+            parameter_value: Some(value.to_owned()),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn same_parameters(current: &Option<Vec<Parameter>>, desired: &HashMap<String, String>) -> bool {
+    let current: HashMap<String, String> = current
+        .iter()
+        .flatten()
+        .filter_map(|p| match (&p.parameter_key, &p.parameter_value) {
+            (Some(key), Some(value)) => Some((key.to_owned(), value.to_owned())),
+            _ => None,
+        })
+        .collect();
+    &current == desired
+}
+
+fn deployment(
+    targets: Vec<String>,
+    service_managed: bool,
+) -> (Option<Vec<String>>, Option<DeploymentTargets>) {
+    if service_managed {
+        let deployment_targets = DeploymentTargets {
+            organizational_unit_ids: Some(targets),
+            ..Default::default()
+        };
+        (None, Some(deployment_targets))
+    } else {
+        (Some(targets), None)
+    }
+}
+
+// The (target, region) pairs of the existing instances, optionally only
+// those in the given status.
+fn deployed(
+    existing: &[StackInstanceSummary],
+    service_managed: bool,
+    status: Option<&str>,
+) -> Vec<(String, String)> {
+    let mut pairs = vec![];
+    for instance in existing {
+        if status.map_or(false, |s| instance.status.as_deref() != Some(s)) {
+            continue;
+        }
+        let target = if service_managed {
+            instance.organizational_unit_id.clone()
+        } else {
+            instance.account.clone()
+        };
+        if let (Some(target), Some(region)) = (target, instance.region.clone()) {
+            if !pairs.contains(&(target.clone(), region.clone())) {
+                pairs.push((target, region));
+            }
+        }
+    }
+    pairs
+}
+
+fn selected(
+    pairs: Vec<(String, String)>,
+    targets: &[String],
+    regions: &[String],
+) -> Vec<(String, String)> {
+    pairs
+        .into_iter()
+        .filter(|(target, region)| targets.contains(target) && regions.contains(region))
+        .collect()
+}
+
+// Groups (target, region) pairs by each target's set of regions, so that
+// each stack instance call covers exactly the given pairs.
+fn group(pairs: Vec<(String, String)>) -> Vec<(Vec<String>, Vec<String>)> {
+    let mut by_target: Vec<(String, Vec<String>)> = vec![];
+    for (target, region) in pairs {
+        match by_target.iter_mut().find(|(t, _)| t == &target) {
+            Some((_, regions)) => regions.push(region),
+            None => by_target.push((target, vec![region])),
+        }
+    }
+    let mut groups: BTreeMap<Vec<String>, Vec<String>> = BTreeMap::new();
+    for (target, regions) in by_target {
+        groups.entry(regions).or_default().push(target);
+    }
+    groups
+        .into_iter()
+        .map(|(regions, targets)| (targets, regions))
+        .collect()
+}
+
+fn missing(
+    existing: &[StackInstanceSummary],
+    targets: &[String],
+    regions: &[String],
+    service_managed: bool,
+) -> Vec<(Vec<String>, Vec<String>)> {
+    let deployed: HashSet<(String, String)> = deployed(existing, service_managed, None)
+        .into_iter()
+        .collect();
+    group(
+        targets
+            .iter()
+            .flat_map(|target| {
+                regions
+                    .iter()
+                    .map(move |region| (target.clone(), region.clone()))
+            })
+            .filter(|pair| !deployed.contains(pair))
+            .collect(),
+    )
+}
+
+// Instances for accounts, organizational units or regions that are no longer
+// listed.
+fn stale(
+    existing: &[StackInstanceSummary],
+    targets: &[String],
+    regions: &[String],
+    service_managed: bool,
+) -> Vec<(Vec<String>, Vec<String>)> {
+    group(
+        deployed(existing, service_managed, None)
+            .into_iter()
+            .filter(|(target, region)| !targets.contains(target) || !regions.contains(region))
+            .collect(),
+    )
+}
+
+// Instances left behind by a failed operation, which are updated again.
+fn outdated(
+    existing: &[StackInstanceSummary],
+    targets: &[String],
+    regions: &[String],
+    service_managed: bool,
+) -> Vec<(Vec<String>, Vec<String>)> {
+    group(selected(
+        deployed(existing, service_managed, Some(INSTANCE_OUTDATED)),
+        targets,
+        regions,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(account: &str, region: &str) -> StackInstanceSummary {
+        StackInstanceSummary {
+            account: Some(account.to_owned()),
+            region: Some(region.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_missing() {
+        let existing = vec![
+            instance("111", "us-east-1"),
+            instance("111", "eu-west-1"),
+            instance("222", "us-east-1"),
+        ];
+        let targets = vec!["111".to_owned(), "222".to_owned(), "333".to_owned()];
+        let regions = vec!["us-east-1".to_owned(), "eu-west-1".to_owned()];
+        assert_eq!(
+            missing(&existing, &targets, &regions, false),
+            vec![
+                (vec!["222".to_owned()], vec!["eu-west-1".to_owned()]),
+                (vec!["333".to_owned()], regions.clone()),
+            ]
+        );
+        assert!(missing(&existing, &targets[..1], &regions, false).is_empty());
+    }
+
+    #[test]
+    fn test_stale_and_outdated() {
+        let mut outdated_instance = instance("222", "us-east-1");
+        outdated_instance.status = Some(INSTANCE_OUTDATED.to_owned());
+        let existing = vec![
+            instance("111", "us-east-1"),
+            instance("111", "eu-west-1"),
+            outdated_instance,
+            instance("444", "us-east-1"),
+        ];
+        let targets = vec!["111".to_owned(), "222".to_owned()];
+        let regions = vec!["us-east-1".to_owned()];
+        assert_eq!(
+            stale(&existing, &targets, &regions, false),
+            vec![
+                (vec!["111".to_owned()], vec!["eu-west-1".to_owned()]),
+                (vec!["444".to_owned()], vec!["us-east-1".to_owned()]),
+            ]
+        );
+        assert_eq!(
+            outdated(&existing, &targets, &regions, false),
+            vec![(vec!["222".to_owned()], vec!["us-east-1".to_owned()])]
+        );
+        assert!(outdated(&existing, &targets[..1], &regions, false).is_empty());
+    }
+
+    #[test]
+    fn test_same_parameters() {
+        let mut desired = HashMap::new();
+        desired.insert("Env".to_owned(), "prod".to_owned());
+        let current = Some(to_parameters(&desired));
+        assert!(same_parameters(&current, &desired));
+        assert!(!same_parameters(&None, &desired));
+        assert!(same_parameters(&None, &HashMap::new()));
+
+        desired.insert("Size".to_owned(), "2".to_owned());
+        assert!(!same_parameters(&current, &desired));
+        assert_eq!(
+            to_parameters(&desired)
+                .into_iter()
+                .map(|p| p.parameter_key.unwrap())
+                .collect::<Vec<String>>(),
+            vec!["Env", "Size"]
+        );
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+use std::default::Default;
+
+use rusoto_ec2::{
+    DescribeImagesRequest, DescribeInstancesRequest, DescribeSecurityGroupsRequest,
+    DescribeSubnetsRequest, Ec2 as EC2, Ec2Client, Filter, Tag,
+};
+use serde::Serialize;
+
+const EC2_INFO: &str = "ec2_info";
+
+const INSTANCE_STATE_NAME: &str = "instance-state-name";
+const RUNNING: &str = "running";
+
+// EC2 filter names (e.g. `name`, `tag:Env`, `vpc-id`) to the values any of
+// which may match.
+pub type Filters = dyn Fn(&crate::ferro::Context) -> HashMap<String, Vec<String>> + Send + Sync;
+
+pub struct Images {
+    pub owners: Box<crate::lazy::Vec<String>>,
+    pub filters: Box<Filters>,
+}
+
+impl Default for Images {
+    fn default() -> Self {
+        Images {
+            owners: Box::new(|_| Ok(vec![])),
+            filters: Box::new(|_| HashMap::new()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Image {
+    image_id: Option<String>,
+    name: Option<String>,
+    creation_date: Option<String>,
+    owner_id: Option<String>,
+    architecture: Option<String>,
+    state: Option<String>,
+    tags: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Instance {
+    instance_id: Option<String>,
+    instance_type: Option<String>,
+    image_id: Option<String>,
+    state: Option<String>,
+    private_ip_address: Option<String>,
+    public_ip_address: Option<String>,
+    subnet_id: Option<String>,
+    vpc_id: Option<String>,
+    launch_time: Option<String>,
+    tags: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Subnet {
+    subnet_id: Option<String>,
+    vpc_id: Option<String>,
+    cidr_block: Option<String>,
+    availability_zone: Option<String>,
+    available_ip_address_count: Option<i64>,
+    tags: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SecurityGroup {
+    group_id: Option<String>,
+    group_name: Option<String>,
+    vpc_id: Option<String>,
+    description: Option<String>,
+    tags: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Output {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<Image>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    images: Vec<Image>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    instances: Vec<Instance>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    subnets: Vec<Subnet>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    security_groups: Vec<SecurityGroup>,
+}
+
+#[typetag::serialize]
+impl crate::ferro::Output for Output {
+    fn to_value(&self) -> Result<serde_json::value::Value, serde_json::error::Error> {
+        serde_json::to_value(self)
+    }
+}
+
+pub struct Ec2Info {
+    pub images: Option<Images>,
+    pub instances: Option<Box<Filters>>,
+    pub subnets: Option<Box<Filters>>,
+    pub security_groups: Option<Box<Filters>>,
+    pub ec2: Ec2Client,
+}
+
+impl Default for Ec2Info {
+    fn default() -> Self {
+        Ec2Info {
+            images: None,
+            instances: None,
+            subnets: None,
+            security_groups: None,
+            ec2: Ec2Client::new(crate::lookup::aws::region()),
+        }
+    }
+}
+
+impl Ec2Info {
+    // Images are sorted newest first; `image` is the newest match.
+    fn images(&self, owners: Vec<String>, filters: Vec<Filter>) -> Result<Vec<Image>, String> {
+        let result = self
+            .ec2
+            .describe_images(DescribeImagesRequest {
+                owners: if owners.is_empty() {
+                    None
+                } else {
+                    Some(owners)
+                },
+                filters: Some(filters),
+                ..Default::default()
+            })
+            .sync()
+            .map_err(|e| e.to_string())?;
+        let mut images: Vec<Image> = result
+            .images
+            .unwrap_or_default()
+            .into_iter()
+            .map(|image| Image {
+                image_id: image.image_id,
+                name: image.name,
+                creation_date: image.creation_date,
+                owner_id: image.owner_id,
+                architecture: image.architecture,
+                state: image.state,
+                tags: tags_to_map(image.tags),
+            })
+            .collect();
+        images.sort_by(|a, b| b.creation_date.cmp(&a.creation_date));
+        Ok(images)
+    }
+
+    fn instances(&self, mut filters: Vec<Filter>) -> Result<Vec<Instance>, String> {
+        if !filters
+            .iter()
+            .any(|f| f.name.as_deref() == Some(INSTANCE_STATE_NAME))
+        {
+            filters.push(Filter {
+                name: Some(INSTANCE_STATE_NAME.to_owned()),
+                values: Some(vec![RUNNING.to_owned()]),
+            });
+        }
+        let mut instances = vec![];
+        let mut next_token = None;
+        loop {
+            let result = self
+                .ec2
+                .describe_instances(DescribeInstancesRequest {
+                    filters: Some(filters.clone()),
+                    next_token: next_token,
+                    ..Default::default()
+                })
+                .sync()
+                .map_err(|e| e.to_string())?;
+            for reservation in result.reservations.unwrap_or_default() {
+                instances.extend(reservation.instances.unwrap_or_default().into_iter().map(
+                    |instance| Instance {
+                        instance_id: instance.instance_id,
+                        instance_type: instance.instance_type,
+                        image_id: instance.image_id,
+                        state: instance.state.and_then(|state| state.name),
+                        private_ip_address: instance.private_ip_address,
+                        public_ip_address: instance.public_ip_address,
+                        subnet_id: instance.subnet_id,
+                        vpc_id: instance.vpc_id,
+                        launch_time: instance.launch_time,
+                        tags: tags_to_map(instance.tags),
+                    },
+                ));
+            }
+            next_token = result.next_token;
+            if next_token.is_none() {
+                return Ok(instances);
+            }
+        }
+    }
+
+    fn subnets(&self, filters: Vec<Filter>) -> Result<Vec<Subnet>, String> {
+        let mut subnets = vec![];
+        let mut next_token = None;
+        loop {
+            let result = self
+                .ec2
+                .describe_subnets(DescribeSubnetsRequest {
+                    filters: Some(filters.clone()),
+                    next_token: next_token,
+                    ..Default::default()
+                })
+                .sync()
+                .map_err(|e| e.to_string())?;
+            subnets.extend(
+                result
+                    .subnets
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|subnet| Subnet {
+                        subnet_id: subnet.subnet_id,
+                        vpc_id: subnet.vpc_id,
+                        cidr_block: subnet.cidr_block,
+                        availability_zone: subnet.availability_zone,
+                        available_ip_address_count: subnet.available_ip_address_count,
+                        tags: tags_to_map(subnet.tags),
+                    }),
+            );
+            next_token = result.next_token;
+            if next_token.is_none() {
+                return Ok(subnets);
+            }
+        }
+    }
+
+    fn security_groups(&self, filters: Vec<Filter>) -> Result<Vec<SecurityGroup>, String> {
+        let mut security_groups = vec![];
+        let mut next_token = None;
+        loop {
+            let result = self
+                .ec2
+                .describe_security_groups(DescribeSecurityGroupsRequest {
+                    filters: Some(filters.clone()),
+                    next_token: next_token,
+                    ..Default::default()
+                })
+                .sync()
+                .map_err(|e| e.to_string())?;
+            security_groups.extend(result.security_groups.unwrap_or_default().into_iter().map(
+                |group| SecurityGroup {
+                    group_id: group.group_id,
+                    group_name: group.group_name,
+                    vpc_id: group.vpc_id,
+                    description: group.description,
+                    tags: tags_to_map(group.tags),
+                },
+            ));
+            next_token = result.next_token;
+            if next_token.is_none() {
+                return Ok(security_groups);
+            }
+        }
+    }
+
+    fn query(&self, context: &crate::ferro::Context) -> Result<Output, String> {
+        let mut output = Output::default();
+        if let Some(images) = &self.images {
+            let owners = (images.owners)(context).map_err(|e| e.0)?;
+            let filters = filters((images.filters)(context));
+            // Without either, DescribeImages lists every public image.
+            if owners.is_empty() && filters.is_empty() {
+                return Err("images requires owners or filters".to_owned());
+            }
+            output.images = self.images(owners, filters)?;
+            output.image = match output.images.first() {
+                Some(image) => Some(image.clone()),
+                None => return Err("no images matched".to_owned()),
+            };
+        }
+        if let Some(instances) = &self.instances {
+            output.instances = self.instances(filters(instances(context)))?;
+        }
+        if let Some(subnets) = &self.subnets {
+            output.subnets = self.subnets(filters(subnets(context)))?;
+        }
+        if let Some(security_groups) = &self.security_groups {
+            output.security_groups = self.security_groups(filters(security_groups(context)))?;
+        }
+        Ok(output)
+    }
+}
+
+impl crate::ferro::Module for Ec2Info {
+    fn name(&self) -> String {
+        EC2_INFO.to_owned()
+    }
+
+    fn apply(
+        &self,
+        context: &crate::ferro::Context,
+    ) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        if self.images.is_none()
+            && self.instances.is_none()
+            && self.subnets.is_none()
+            && self.security_groups.is_none()
+        {
+            return crate::ferro::result_error(
+                false,
+                "one of images, instances, subnets or security_groups is required".to_owned(),
+            );
+        }
+        match self.query(context) {
+            Ok(output) => crate::ferro::result_response(false, Some(Box::new(output))),
+            Err(e) => crate::ferro::result_error(false, e),
+        }
+    }
+
+    fn destroy(&self) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        crate::ferro::result_response(false, None)
+    }
+}
+
+fn filters(filters: HashMap<String, Vec<String>>) -> Vec<Filter> {
+    let mut filters: Vec<Filter> = filters
+        .into_iter()
+        .map(|(name, values)| Filter {
+            name: Some(name),
+            values: Some(values),
+        })
+        .collect();
+    filters.sort_by(|a, b| a.name.cmp(&b.name));
+    filters
+}
+
+fn tags_to_map(tags: Option<Vec<Tag>>) -> HashMap<String, String> {
+    tags.unwrap_or_default()
+        .into_iter()
+        .filter_map(|t| match (t.key, t.value) {
+            (Some(k), Some(v)) => Some((k, v)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters() {
+        let mut input = HashMap::new();
+        input.insert("name".to_owned(), vec!["amzn2-ami-hvm-*".to_owned()]);
+        input.insert(
+            "architecture".to_owned(),
+            vec!["x86_64".to_owned(), "arm64".to_owned()],
+        );
+        input.insert("tag:Name".to_owned(), vec!["web, api".to_owned()]);
+        assert_eq!(
+            filters(input),
+            vec![
+                Filter {
+                    name: Some("architecture".to_owned()),
+                    values: Some(vec!["x86_64".to_owned(), "arm64".to_owned()]),
+                },
+                Filter {
+                    name: Some("name".to_owned()),
+                    values: Some(vec!["amzn2-ami-hvm-*".to_owned()]),
+                },
+                Filter {
+                    name: Some("tag:Name".to_owned()),
+                    values: Some(vec!["web, api".to_owned()]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_images_require_owners_or_filters() {
+        let ec2_info = Ec2Info {
+            images: Some(Default::default()),
+            ..Default::default()
+        };
+        let context = crate::ferro::Context::default();
+        assert_eq!(
+            ec2_info.query(&context).unwrap_err(),
+            "images requires owners or filters"
+        );
+    }
+}
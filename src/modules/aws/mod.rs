@@ -1 +1,5 @@
 pub mod cloudformation;
+pub mod cloudformation_info;
+pub mod cloudformation_stackset;
+pub mod ec2_info;
+pub mod s3;
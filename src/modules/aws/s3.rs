@@ -0,0 +1,584 @@
+use std::collections::HashMap;
+use std::default::Default;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use rusoto_core::RusotoError;
+use rusoto_s3::{
+    CopyObjectRequest, DeleteObjectRequest, GetObjectRequest, HeadObjectError, HeadObjectOutput,
+    HeadObjectRequest, ListObjectsV2Request, PutObjectRequest, S3Client, S3 as S3Api,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+const S3: &str = "s3";
+
+// Checksums and canned ACLs are recorded as object metadata, so an object is
+// current when its stored metadata matches what would be uploaded. ETags are
+// not used as they are not content hashes for multipart or encrypted objects.
+const SHA256_METADATA: &str = "sha256";
+const ACL_METADATA: &str = "acl";
+
+const METADATA_DIRECTIVE_REPLACE: &str = "REPLACE";
+
+#[derive(Debug)]
+pub enum Error {
+    S3Error(String),
+    IoError(String),
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::S3Error(e) => write!(f, "s3 error: {}", e),
+            Error::IoError(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Put,
+    Get,
+    Delete,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::Put
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Output {
+    bucket: String,
+    changed: Vec<String>,
+    unchanged: Vec<String>,
+}
+
+#[typetag::serialize]
+impl crate::ferro::Output for Output {
+    fn to_value(&self) -> Result<serde_json::value::Value, serde_json::error::Error> {
+        serde_json::to_value(self)
+    }
+}
+
+pub struct S3Object {
+    pub bucket: Box<crate::lazy::String>,
+    pub key: Box<crate::lazy::String>,
+    pub src: Box<crate::lazy::String>,
+    pub dest: Box<crate::lazy::String>,
+    pub mode: Mode,
+    pub metadata: Box<dyn Fn(&crate::ferro::Context) -> HashMap<String, String> + Send + Sync>,
+    pub content_type: Box<crate::lazy::String>,
+    pub acl: Box<crate::lazy::String>,
+    pub prune: bool,
+    pub s3: S3Client,
+}
+
+impl Default for S3Object {
+    fn default() -> Self {
+        S3Object {
+            bucket: Box::new(crate::lazy::string("".to_owned())),
+            key: Box::new(crate::lazy::string("".to_owned())),
+            src: Box::new(crate::lazy::string("".to_owned())),
+            dest: Box::new(crate::lazy::string("".to_owned())),
+            mode: Default::default(),
+            metadata: Box::new(|_| HashMap::new()),
+            content_type: Box::new(crate::lazy::string("".to_owned())),
+            acl: Box::new(crate::lazy::string("".to_owned())),
+            prune: false,
+            s3: S3Client::new(crate::lookup::aws::region()),
+        }
+    }
+}
+
+struct Upload {
+    metadata: HashMap<String, String>,
+    content_type: String,
+    acl: String,
+}
+
+impl S3Object {
+    fn head(&self, bucket: &str, key: &str) -> Result<Option<HeadObjectOutput>, Error> {
+        let result = self
+            .s3
+            .head_object(HeadObjectRequest {
+                bucket: bucket.to_owned(),
+                key: key.to_owned(),
+                ..Default::default()
+            })
+            .sync();
+        match result {
+            Ok(output) => Ok(Some(output)),
+            Err(RusotoError::Service(HeadObjectError::NoSuchKey(_))) => Ok(None),
+            Err(RusotoError::Unknown(ref response)) if response.status.as_u16() == 404 => Ok(None),
+            Err(e) => Err(Error::S3Error(e.to_string())),
+        }
+    }
+
+    fn list(&self, bucket: &str, prefix: &str) -> Result<Vec<String>, Error> {
+        let mut keys = vec![];
+        let mut continuation_token = None;
+        loop {
+            let result = self
+                .s3
+                .list_objects_v2(ListObjectsV2Request {
+                    bucket: bucket.to_owned(),
+                    prefix: Some(prefix.to_owned()),
+                    continuation_token: continuation_token,
+                    ..Default::default()
+                })
+                .sync()
+                .map_err(|e| Error::S3Error(e.to_string()))?;
+            keys.extend(
+                result
+                    .contents
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|object| object.key),
+            );
+            continuation_token = result.next_continuation_token;
+            if continuation_token.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+
+    fn put(&self, bucket: &str, key: &str, path: &Path, upload: &Upload) -> Result<bool, Error> {
+        let contents =
+            fs::read(path).map_err(|e| Error::IoError(format!("{}: {}", path.display(), e)))?;
+        let mut metadata = upload.metadata.clone();
+        metadata.insert(SHA256_METADATA.to_owned(), sha256(&contents));
+        if upload.acl != "" {
+            metadata.insert(ACL_METADATA.to_owned(), upload.acl.to_owned());
+        }
+
+        let existing = self.head(bucket, key)?;
+        let same_contents = existing.as_ref().map_or(false, |head| {
+            head.metadata.as_ref().and_then(|m| m.get(SHA256_METADATA))
+                == metadata.get(SHA256_METADATA)
+        });
+        let same_metadata = existing.as_ref().map_or(false, |head| {
+            head.metadata.as_ref() == Some(&metadata)
+                && (upload.content_type == ""
+                    || head.content_type.as_ref() == Some(&upload.content_type))
+        });
+        if same_contents && same_metadata {
+            return Ok(false);
+        }
+
+        if same_contents {
+            self.s3
+                .copy_object(CopyObjectRequest {
+                    bucket: bucket.to_owned(),
+                    key: key.to_owned(),
+                    copy_source: format!("{}/{}", bucket, encode_key(key)),
+                    metadata: Some(metadata),
+                    metadata_directive: Some(METADATA_DIRECTIVE_REPLACE.to_owned()),
+                    content_type: non_empty(&upload.content_type),
+                    acl: non_empty(&upload.acl),
+                    ..Default::default()
+                })
+                .sync()
+                .map_err(|e| Error::S3Error(e.to_string()))?;
+        } else {
+            self.s3
+                .put_object(PutObjectRequest {
+                    bucket: bucket.to_owned(),
+                    key: key.to_owned(),
+                    body: Some(contents.into()),
+                    metadata: Some(metadata),
+                    content_type: non_empty(&upload.content_type),
+                    acl: non_empty(&upload.acl),
+                    ..Default::default()
+                })
+                .sync()
+                .map_err(|e| Error::S3Error(e.to_string()))?;
+        }
+        Ok(true)
+    }
+
+    fn get(&self, bucket: &str, key: &str, path: &Path) -> Result<bool, Error> {
+        let local = fs::read(path).ok();
+        if let Some(local) = &local {
+            let stored = self
+                .head(bucket, key)?
+                .and_then(|head| head.metadata)
+                .and_then(|mut metadata| metadata.remove(SHA256_METADATA));
+            if stored == Some(sha256(local)) {
+                return Ok(false);
+            }
+        }
+
+        let output = self
+            .s3
+            .get_object(GetObjectRequest {
+                bucket: bucket.to_owned(),
+                key: key.to_owned(),
+                ..Default::default()
+            })
+            .sync()
+            .map_err(|e| Error::S3Error(e.to_string()))?;
+        let mut contents = vec![];
+        if let Some(body) = output.body {
+            body.into_blocking_read()
+                .read_to_end(&mut contents)
+                .map_err(|e| Error::IoError(e.to_string()))?;
+        }
+        if local.as_ref() == Some(&contents) {
+            return Ok(false);
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| Error::IoError(format!("{}: {}", parent.display(), e)))?;
+        }
+        fs::write(path, contents)
+            .map_err(|e| Error::IoError(format!("{}: {}", path.display(), e)))?;
+        Ok(true)
+    }
+
+    fn delete(&self, bucket: &str, key: &str) -> Result<bool, Error> {
+        if self.head(bucket, key)?.is_none() {
+            return Ok(false);
+        }
+        self.s3
+            .delete_object(DeleteObjectRequest {
+                bucket: bucket.to_owned(),
+                key: key.to_owned(),
+                ..Default::default()
+            })
+            .sync()
+            .map_err(|e| Error::S3Error(e.to_string()))?;
+        Ok(true)
+    }
+
+    // Syncs the key with the local path, which is the source of a put and the
+    // destination of a get.
+    fn sync(
+        &self,
+        bucket: &str,
+        key: &str,
+        local: &Path,
+        upload: &Upload,
+        output: &mut Output,
+    ) -> Result<(), Error> {
+        match self.mode {
+            Mode::Put => {
+                let src = local;
+                // A single file is uploaded to the key itself unless the key
+                // is a prefix, in which case its file name is appended.
+                let files = if src.is_dir() || key == "" || key.ends_with('/') {
+                    local_files(src)?
+                } else {
+                    vec![("".to_owned(), src.to_owned())]
+                };
+                for (relative, path) in &files {
+                    let object_key = object_key(key, relative);
+                    record(output, object_key.to_owned(), |k| {
+                        self.put(bucket, k, path, upload)
+                    })?;
+                }
+                if self.prune && src.is_dir() {
+                    let keys: Vec<String> = files
+                        .iter()
+                        .map(|(relative, _)| object_key(key, relative))
+                        .collect();
+                    for remote in self.list(bucket, &prefix(key))? {
+                        if !keys.contains(&remote) {
+                            record(output, remote, |k| self.delete(bucket, k))?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Mode::Get => {
+                let dest = local;
+                if key == "" || key.ends_with('/') {
+                    let prefix = prefix(key);
+                    for remote in self.list(bucket, &prefix)? {
+                        if remote.ends_with('/') {
+                            continue;
+                        }
+                        let path = local_path(dest, &remote[prefix.len()..])?;
+                        record(output, remote, |k| self.get(bucket, k, &path))?;
+                    }
+                    Ok(())
+                } else {
+                    record(output, key.to_owned(), |k| self.get(bucket, k, dest))
+                }
+            }
+            Mode::Delete => {
+                if key.ends_with('/') {
+                    for remote in self.list(bucket, key)? {
+                        record(output, remote, |k| self.delete(bucket, k))?;
+                    }
+                    Ok(())
+                } else {
+                    record(output, key.to_owned(), |k| self.delete(bucket, k))
+                }
+            }
+        }
+    }
+}
+
+impl crate::ferro::Module for S3Object {
+    fn name(&self) -> String {
+        S3.to_owned()
+    }
+
+    fn apply(
+        &self,
+        context: &crate::ferro::Context,
+    ) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        let bucket = (self.bucket)(context)?;
+        let key = (self.key)(context)?;
+        let local = match self.mode {
+            Mode::Put => (self.src)(context)?,
+            Mode::Get => (self.dest)(context)?,
+            Mode::Delete => "".to_owned(),
+        };
+        let upload = Upload {
+            metadata: (self.metadata)(context)
+                .into_iter()
+                .map(|(k, v)| (k.to_lowercase(), v))
+                .collect(),
+            content_type: (self.content_type)(context)?,
+            acl: (self.acl)(context)?,
+        };
+        if bucket == "" {
+            return crate::ferro::result_error(false, "bucket is required".to_owned());
+        }
+        match self.mode {
+            Mode::Put if local == "" => {
+                return crate::ferro::result_error(false, "src is required".to_owned())
+            }
+            Mode::Get if local == "" => {
+                return crate::ferro::result_error(false, "dest is required".to_owned())
+            }
+            Mode::Delete if key == "" => {
+                return crate::ferro::result_error(false, "key is required".to_owned())
+            }
+            _ => {}
+        }
+
+        let mut output = Output {
+            bucket: bucket.to_owned(),
+            ..Default::default()
+        };
+        let result = self.sync(&bucket, &key, Path::new(&local), &upload, &mut output);
+        let changed = !output.changed.is_empty();
+        match result {
+            Ok(_) => crate::ferro::result_response(changed, Some(Box::new(output))),
+            Err(e) => {
+                crate::ferro::result_error_with_output(changed, e.to_string(), Box::new(output))
+            }
+        }
+    }
+
+    fn destroy(&self) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        crate::ferro::result_response(false, None)
+    }
+}
+
+fn record<F>(output: &mut Output, key: String, f: F) -> Result<(), Error>
+where
+    F: FnOnce(&str) -> Result<bool, Error>,
+{
+    if f(&key)? {
+        output.changed.push(key);
+    } else {
+        output.unchanged.push(key);
+    }
+    Ok(())
+}
+
+fn local_files(src: &Path) -> Result<Vec<(String, PathBuf)>, Error> {
+    if !src.is_dir() {
+        let name = src
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        return Ok(vec![(name, src.to_path_buf())]);
+    }
+    let mut files = vec![];
+    let mut dirs = vec![src.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries =
+            fs::read_dir(&dir).map_err(|e| Error::IoError(format!("{}: {}", dir.display(), e)))?;
+        for entry in entries {
+            let path = entry.map_err(|e| Error::IoError(e.to_string()))?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if let Ok(relative) = path.strip_prefix(src) {
+                let relative = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                    .collect::<Vec<String>>()
+                    .join("/");
+                files.push((relative, path.to_owned()));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn prefix(key: &str) -> String {
+    if key == "" || key.ends_with('/') {
+        key.to_owned()
+    } else {
+        format!("{}/", key)
+    }
+}
+
+fn object_key(key: &str, relative: &str) -> String {
+    if relative == "" {
+        key.to_owned()
+    } else {
+        format!("{}{}", prefix(key), relative)
+    }
+}
+
+// Object keys are untrusted, so only plain relative paths are written below
+// `dest`.
+fn local_path(dest: &Path, relative: &str) -> Result<PathBuf, Error> {
+    let path = Path::new(relative);
+    if path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        Ok(dest.join(path))
+    } else {
+        Err(Error::IoError(format!(
+            "refusing to write {} outside {}",
+            relative,
+            dest.display()
+        )))
+    }
+}
+
+// CopySource is a URL path, so everything but unreserved characters and the
+// separators is percent-encoded.
+fn encode_key(key: &str) -> String {
+    let mut encoded = String::new();
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn sha256(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    if s == "" {
+        None
+    } else {
+        Some(s.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("css")).unwrap();
+        fs::write(root.join("index.html"), "index").unwrap();
+        fs::write(root.join("css").join("site.css"), "body").unwrap();
+
+        let keys: Vec<String> = local_files(root)
+            .unwrap()
+            .iter()
+            .map(|(relative, _)| object_key("site", relative))
+            .collect();
+        assert_eq!(keys, vec!["site/css/site.css", "site/index.html"]);
+        assert_eq!(object_key("site/", "index.html"), "site/index.html");
+        assert_eq!(object_key("", "index.html"), "index.html");
+        assert_eq!(object_key("index.html", ""), "index.html");
+    }
+
+    #[test]
+    fn test_local_path() {
+        let dest = Path::new("/srv/site");
+        assert_eq!(
+            local_path(dest, "css/site.css").unwrap(),
+            PathBuf::from("/srv/site/css/site.css")
+        );
+        assert!(local_path(dest, "../etc/passwd").is_err());
+        assert!(local_path(dest, "css/../../etc/passwd").is_err());
+        assert!(local_path(dest, "/etc/passwd").is_err());
+        assert!(local_path(dest, "./index.html").is_err());
+    }
+
+    #[test]
+    fn test_encode_key() {
+        assert_eq!(encode_key("site/index.html"), "site/index.html");
+        assert_eq!(encode_key("a b+c&d?.txt"), "a%20b%2Bc%26d%3F.txt");
+        assert_eq!(encode_key("café"), "caf%C3%A9");
+    }
+
+    // Runs against a local S3-compatible server, e.g.
+    // AWS_ENDPOINT_URL=http://localhost:9000 with an existing ferro-test bucket.
+    #[test]
+    #[ignore]
+    fn test_s3_local_endpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("hello.txt"), "hello").unwrap();
+        let context = crate::ferro::Context::default();
+
+        let put = S3Object {
+            bucket: Box::new(crate::lazy::string("ferro-test".to_owned())),
+            key: Box::new(crate::lazy::string("upload".to_owned())),
+            src: Box::new(crate::lazy::string(root.display().to_string())),
+            ..Default::default()
+        };
+        let module: &dyn crate::ferro::Module = &put;
+        assert!(module.apply(&context).unwrap().changed);
+        assert!(!module.apply(&context).unwrap().changed);
+
+        let get = S3Object {
+            bucket: Box::new(crate::lazy::string("ferro-test".to_owned())),
+            key: Box::new(crate::lazy::string("upload/hello.txt".to_owned())),
+            dest: Box::new(crate::lazy::string(
+                root.join("fetched").display().to_string(),
+            )),
+            mode: Mode::Get,
+            ..Default::default()
+        };
+        let module: &dyn crate::ferro::Module = &get;
+        assert!(module.apply(&context).unwrap().changed);
+        assert!(!module.apply(&context).unwrap().changed);
+        assert_eq!(fs::read_to_string(root.join("fetched")).unwrap(), "hello");
+
+        let delete = S3Object {
+            bucket: Box::new(crate::lazy::string("ferro-test".to_owned())),
+            key: Box::new(crate::lazy::string("upload/".to_owned())),
+            mode: Mode::Delete,
+            ..Default::default()
+        };
+        let module: &dyn crate::ferro::Module = &delete;
+        assert!(module.apply(&context).unwrap().changed);
+        assert!(!module.apply(&context).unwrap().changed);
+    }
+}
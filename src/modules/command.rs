@@ -1,8 +1,7 @@
 use std::default::Default;
 use std::error;
 use std::fmt;
-use std::process;
-use std::string;
+use std::os::unix::process::ExitStatusExt;
 use std::vec::Vec;
 
 use serde::Serialize;
@@ -24,23 +23,8 @@ impl error::Error for Error {
 impl From<Error> for crate::ferro::Error {
     fn from(e: Error) -> Self {
         match e {
-            Error::InvalidCommandError => crate::ferro::Error {
-                changed: false,
-                description: "invalid command".to_owned(),
-            },
-            Error::CommandError => crate::ferro::Error {
-                changed: false,
-                description: "command error".to_owned(),
-            },
-        }
-    }
-}
-
-impl From<string::FromUtf8Error> for crate::ferro::Error {
-    fn from(e: string::FromUtf8Error) -> Self {
-        crate::ferro::Error {
-            changed: true,
-            description: e.to_string(),
+            Error::InvalidCommandError => crate::ferro::error(false, "invalid command".to_owned()),
+            Error::CommandError => crate::ferro::error(false, "command error".to_owned()),
         }
     }
 }
@@ -54,20 +38,35 @@ impl fmt::Display for Error {
 #[derive(Debug, Serialize)]
 pub struct Output {
     exit_status: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signal: Option<i32>,
+    duration: f64,
     stdout: String,
     stderr: String,
     stdout_lines: Vec<String>,
     stderr_lines: Vec<String>,
+    stdout_truncated: bool,
+    stderr_truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stdout_base64: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stderr_base64: Option<String>,
 }
 
 impl Default for Output {
     fn default() -> Self {
         Output {
             exit_status: 0,
+            signal: None,
+            duration: 0.0,
             stdout: "".to_owned(),
             stderr: "".to_owned(),
             stdout_lines: vec![],
             stderr_lines: vec![],
+            stdout_truncated: false,
+            stderr_truncated: false,
+            stdout_base64: None,
+            stderr_base64: None,
         }
     }
 }
@@ -89,10 +88,10 @@ pub struct Command {
 impl Default for Command {
     fn default() -> Self {
         Command {
-            command: Box::new(|_| "".to_owned()),
-            args: Box::new(|_| vec![]),
-            creates: Box::new(|_| "".to_owned()),
-            removes: Box::new(|_| "".to_owned()),
+            command: Box::new(|_| Ok("".to_owned())),
+            args: Box::new(|_| Ok(vec![])),
+            creates: Box::new(|_| Ok("".to_owned())),
+            removes: Box::new(|_| Ok("".to_owned())),
         }
     }
 }
@@ -106,40 +105,56 @@ impl crate::ferro::Module for Command {
         &self,
         context: &crate::ferro::Context,
     ) -> Result<crate::ferro::Response, crate::ferro::Error> {
-        let args: Vec<String> = (self.args)(context)
+        let args = (self.args)(context)?
             .into_iter()
             .map(|f| f(context))
-            .collect();
-        let result = process::Command::new((self.command)(context))
-            .args(args)
-            .stdin(process::Stdio::null())
-            .stdout(process::Stdio::piped())
-            .stderr(process::Stdio::piped())
-            .spawn()
-            .and_then(|child| child.wait_with_output());
-
-        match result {
-            Ok(out) => {
-                let stdout = String::from_utf8(out.stdout)?;
-                let stderr = String::from_utf8(out.stderr)?;
-                let output = Output {
-                    exit_status: out.status.code().unwrap_or(-1),
-                    stdout: stdout.clone(),
-                    stderr: stderr.clone(),
-                    stdout_lines: stdout.lines().map(|l| l.to_owned()).collect(),
-                    stderr_lines: stderr.lines().map(|l| l.to_owned()).collect(),
-                };
-                if out.status.success() {
-                    crate::ferro::result_response(true, Some(Box::new(output)))
-                } else {
-                    crate::ferro::result_error(true, stderr)
-                }
-            }
-            Err(e) => crate::ferro::result_error(true, e.to_string()),
-        }
+            .collect::<Result<Vec<String>, _>>()?;
+        execute((self.command)(context)?, args, context)
     }
 
     fn destroy(&self) -> Result<crate::ferro::Response, crate::ferro::Error> {
         crate::ferro::result_response(false, None)
     }
 }
+
+pub fn execute(
+    command: String,
+    args: Vec<String>,
+    context: &crate::ferro::Context,
+) -> Result<crate::ferro::Response, crate::ferro::Error> {
+    let output = crate::command::Stream {
+        prefix: context.task.clone(),
+        quiet: context.no_log || !context.stream_output,
+        redact: context.secret_values(),
+    };
+    match context.connection.run(command, args, &output) {
+        Ok(out) => {
+            let (stdout, stdout_base64) = crate::command::decode(out.stdout);
+            let (stderr, stderr_base64) = crate::command::decode(out.stderr);
+            let output = Output {
+                exit_status: out.status.code().unwrap_or(-1),
+                signal: out.status.signal(),
+                duration: out.duration.as_secs_f64(),
+                stdout: stdout.clone(),
+                stderr: stderr.clone(),
+                stdout_lines: stdout.lines().map(|l| l.to_owned()).collect(),
+                stderr_lines: stderr.lines().map(|l| l.to_owned()).collect(),
+                stdout_truncated: out.stdout_truncated,
+                stderr_truncated: out.stderr_truncated,
+                stdout_base64: stdout_base64,
+                stderr_base64: stderr_base64,
+            };
+            if out.status.success() {
+                crate::ferro::result_response(true, Some(Box::new(output)))
+            } else {
+                let description = if stderr.trim() != "" {
+                    stderr
+                } else {
+                    format!("command failed: {}", out.status)
+                };
+                crate::ferro::result_error_with_output(true, description, Box::new(output))
+            }
+        }
+        Err(e) => crate::ferro::result_error(true, e.to_string()),
+    }
+}
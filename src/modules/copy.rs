@@ -0,0 +1,101 @@
+use std::default::Default;
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+
+const COPY: &str = "copy";
+
+#[derive(Debug, Serialize)]
+pub struct Output {
+    src: String,
+    dest: String,
+}
+
+#[typetag::serialize]
+impl crate::ferro::Output for Output {
+    fn to_value(&self) -> Result<serde_json::value::Value, serde_json::error::Error> {
+        serde_json::to_value(self)
+    }
+}
+
+pub struct Copy {
+    pub src: Box<crate::lazy::String>,
+    pub dest: Box<crate::lazy::String>,
+}
+
+impl Default for Copy {
+    fn default() -> Self {
+        Copy {
+            src: Box::new(|_| Ok("".to_owned())),
+            dest: Box::new(|_| Ok("".to_owned())),
+        }
+    }
+}
+
+impl Copy {
+    fn is_current(&self, context: &crate::ferro::Context, src: &str, dest: &str) -> bool {
+        tempfile::NamedTempFile::new()
+            .and_then(|fetched| {
+                context.connection.get(dest, fetched.path())?;
+                Ok(fs::read(fetched.path())? == fs::read(src)?)
+            })
+            .unwrap_or(false)
+    }
+}
+
+impl crate::ferro::Module for Copy {
+    fn name(&self) -> String {
+        COPY.to_owned()
+    }
+
+    fn apply(
+        &self,
+        context: &crate::ferro::Context,
+    ) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        let src = (self.src)(context)?;
+        let dest = (self.dest)(context)?;
+        if src == "" || dest == "" {
+            return crate::ferro::result_error(false, "src and dest are required".to_owned());
+        }
+        let output = Output {
+            src: src.clone(),
+            dest: dest.clone(),
+        };
+
+        if self.is_current(context, &src, &dest) {
+            return crate::ferro::result_response(false, Some(Box::new(output)));
+        }
+        match context.connection.put(Path::new(&src), &dest) {
+            Ok(_) => crate::ferro::result_response(true, Some(Box::new(output))),
+            Err(e) => crate::ferro::result_error(false, e.to_string()),
+        }
+    }
+
+    fn destroy(&self) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        crate::ferro::result_response(false, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ferro::Module;
+
+    #[test]
+    fn test_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dest = dir.path().join("dest");
+        fs::write(&src, "contents").unwrap();
+
+        let module = Copy {
+            src: Box::new(crate::lazy::string(src.to_string_lossy().into_owned())),
+            dest: Box::new(crate::lazy::string(dest.to_string_lossy().into_owned())),
+        };
+        let context = Default::default();
+        assert!(module.apply(&context).unwrap().changed);
+        assert!(!module.apply(&context).unwrap().changed);
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "contents");
+    }
+}
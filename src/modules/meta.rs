@@ -0,0 +1,26 @@
+pub const FLUSH_HANDLERS: &str = "flush_handlers";
+
+pub struct FlushHandlers;
+
+impl Default for FlushHandlers {
+    fn default() -> Self {
+        FlushHandlers
+    }
+}
+
+impl crate::ferro::Module for FlushHandlers {
+    fn name(&self) -> String {
+        FLUSH_HANDLERS.to_owned()
+    }
+
+    fn apply(
+        &self,
+        _context: &crate::ferro::Context,
+    ) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        crate::ferro::result_response(false, None)
+    }
+
+    fn destroy(&self) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        crate::ferro::result_response(false, None)
+    }
+}
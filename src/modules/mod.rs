@@ -1,2 +1,6 @@
 pub mod aws;
 pub mod command;
+pub mod copy;
+pub mod meta;
+pub mod shell;
+pub mod template;
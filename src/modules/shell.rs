@@ -0,0 +1,91 @@
+use std::default::Default;
+use std::vec::Vec;
+
+const SHELL: &str = "shell";
+
+const DEFAULT_EXECUTABLE: &str = "/bin/sh";
+const DEFAULT_FLAG: &str = "-c";
+
+pub struct Shell {
+    pub script: Box<crate::lazy::String>,
+    pub executable: Box<crate::lazy::String>,
+    pub flag: Box<crate::lazy::String>,
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Shell {
+            script: Box::new(|_| Ok("".to_owned())),
+            executable: Box::new(crate::lazy::string(DEFAULT_EXECUTABLE.to_owned())),
+            flag: Box::new(crate::lazy::string(DEFAULT_FLAG.to_owned())),
+        }
+    }
+}
+
+impl crate::ferro::Module for Shell {
+    fn name(&self) -> String {
+        SHELL.to_owned()
+    }
+
+    fn apply(
+        &self,
+        context: &crate::ferro::Context,
+    ) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        let script = (self.script)(context)?;
+        if script == "" {
+            return crate::ferro::result_error(false, "script is empty".to_owned());
+        }
+        let args: Vec<String> = vec![(self.flag)(context)?, script];
+        crate::modules::command::execute((self.executable)(context)?, args, context)
+    }
+
+    fn destroy(&self) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        crate::ferro::result_response(false, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ferro::Module;
+
+    #[test]
+    fn test_shell() {
+        let context = crate::ferro::Context::default();
+        let shell = Shell {
+            script: Box::new(crate::lazy::string("echo hello; echo err >&2".to_owned())),
+            ..Default::default()
+        };
+        let response = shell.apply(&context).unwrap();
+        assert!(response.changed);
+        let value = response.output.unwrap().to_value().unwrap();
+        assert_eq!(value["stdout"], "hello\n");
+        assert_eq!(value["stderr"], "err\n");
+        assert_eq!(value["exit_status"], 0);
+
+        let failing = Shell {
+            script: Box::new(crate::lazy::string("echo broken >&2; exit 3".to_owned())),
+            ..Default::default()
+        };
+        let error = failing.apply(&context).err().unwrap();
+        assert!(error.changed);
+        assert_eq!(error.description, "broken\n");
+        assert_eq!(error.output.unwrap().to_value().unwrap()["exit_status"], 3);
+
+        let bash = Shell {
+            script: Box::new(crate::lazy::string("printf %s \"$0\"".to_owned())),
+            executable: Box::new(crate::lazy::string("/bin/bash".to_owned())),
+            ..Default::default()
+        };
+        let value = bash
+            .apply(&context)
+            .unwrap()
+            .output
+            .unwrap()
+            .to_value()
+            .unwrap();
+        assert_eq!(value["stdout"], "/bin/bash");
+
+        assert!(Shell::default().apply(&context).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use std::default::Default;
+use std::fs;
+
+use serde::Serialize;
+
+const TEMPLATE: &str = "template";
+
+#[derive(Debug, Serialize)]
+pub struct Output {
+    src: String,
+    dest: String,
+}
+
+#[typetag::serialize]
+impl crate::ferro::Output for Output {
+    fn to_value(&self) -> Result<serde_json::value::Value, serde_json::error::Error> {
+        serde_json::to_value(self)
+    }
+}
+
+pub struct Template {
+    pub src: Box<crate::lazy::String>,
+    pub dest: Box<crate::lazy::String>,
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Template {
+            src: Box::new(|_| Ok("".to_owned())),
+            dest: Box::new(|_| Ok("".to_owned())),
+        }
+    }
+}
+
+impl Template {
+    fn render(&self, context: &crate::ferro::Context, src: &str) -> Result<String, String> {
+        let contents = fs::read_to_string(src).map_err(|e| format!("{}: {}", src, e))?;
+        crate::template::render_context(&contents, context).map_err(|e| format!("{}: {}", src, e))
+    }
+
+    fn is_current(&self, context: &crate::ferro::Context, rendered: &str, dest: &str) -> bool {
+        tempfile::NamedTempFile::new()
+            .and_then(|fetched| {
+                context.connection.get(dest, fetched.path())?;
+                Ok(fs::read(fetched.path())? == rendered.as_bytes())
+            })
+            .unwrap_or(false)
+    }
+}
+
+impl crate::ferro::Module for Template {
+    fn name(&self) -> String {
+        TEMPLATE.to_owned()
+    }
+
+    fn apply(
+        &self,
+        context: &crate::ferro::Context,
+    ) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        let src = (self.src)(context)?;
+        let dest = (self.dest)(context)?;
+        if src == "" || dest == "" {
+            return crate::ferro::result_error(false, "src and dest are required".to_owned());
+        }
+        let rendered = match self.render(context, &src) {
+            Ok(rendered) => rendered,
+            Err(e) => return crate::ferro::result_error(false, e),
+        };
+        let output = Output {
+            src: src.clone(),
+            dest: dest.clone(),
+        };
+
+        if self.is_current(context, &rendered, &dest) {
+            return crate::ferro::result_response(false, Some(Box::new(output)));
+        }
+        let result = tempfile::NamedTempFile::new().and_then(|staged| {
+            fs::write(staged.path(), rendered)?;
+            context.connection.put(staged.path(), &dest)
+        });
+        match result {
+            Ok(_) => crate::ferro::result_response(true, Some(Box::new(output))),
+            Err(e) => crate::ferro::result_error(false, e.to_string()),
+        }
+    }
+
+    fn destroy(&self) -> Result<crate::ferro::Response, crate::ferro::Error> {
+        crate::ferro::result_response(false, None)
+    }
+}
@@ -22,7 +22,8 @@ macro_rules! playbook {
                 $( $field: Box::new($field_value), )*
                 ..Default::default()
             }),
-            when: playbook! { @when $($when)? }
+            when: playbook! { @when $($when)? },
+            ..Default::default()
         }
     }};
 
@@ -30,7 +31,6 @@ macro_rules! playbook {
         vars { $($key:tt: $value:tt),* }
         $( task $description:tt $rest:tt )*
     ) => {{
-        use ::serde_json::value::Value;
         use ::std::collections::HashMap;
 
         let mut vars = HashMap::<String, String>::new();
@@ -42,9 +42,10 @@ macro_rules! playbook {
         crate::ferro::Playbook {
             context: crate::ferro::Context {
                 vars: vars,
-                state: HashMap::<String, Value>::new(),
+                ..Default::default()
             },
             tasks: tasks,
+            ..Default::default()
         }
     }};
 }
@@ -74,9 +75,9 @@ mod tests {
                 module: Command {
                     command: crate::lazy::string("ls".to_owned()),
                     args: |_| {
-                        vec![
+                        Ok(vec![
                             Box::new(crate::lazy::string("/etc".to_owned())),
-                        ]
+                        ])
                     }
                 },
                 when: (crate::when::when_execute("/bin/true"))
@@ -86,9 +87,9 @@ mod tests {
                 module: Command {
                     command: crate::lazy::string("/bin/echo".to_owned()),
                     args: |_| {
-                        vec![
+                        Ok(vec![
                             Box::new(crate::lazy::var("bye".to_owned())),
-                        ]
+                        ])
                     }
                 }
             }
@@ -103,7 +104,6 @@ mod tests {
             }
         };
 
-        let results = pb.run();
-        assert!(results.into_iter().all(|r| r.succeeded));
+        assert!(pb.run().succeeded());
     }
 }
@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use super::{host, Reporter};
+use crate::ferro::Status;
+
+pub const HUMAN: &str = "human";
+
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+const SLOWEST: usize = 5;
+
+#[derive(Default)]
+struct Recap {
+    ok: usize,
+    changed: usize,
+    failed: usize,
+    skipped: usize,
+    rescued: usize,
+}
+
+pub struct Human {
+    out: Box<dyn io::Write + Send>,
+    colored: bool,
+}
+
+impl Human {
+    pub fn new(out: Box<dyn io::Write + Send>, colored: bool) -> Self {
+        Human {
+            out: out,
+            colored: colored,
+        }
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.colored {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_owned()
+        }
+    }
+}
+
+impl Reporter for Human {
+    fn task_start(&mut self, task: &crate::ferro::Task, context: &crate::ferro::Context) {
+        let host = context
+            .host
+            .as_ref()
+            .map_or(super::LOCALHOST, |h| h.as_str());
+        let _ = writeln!(self.out, "TASK [{}] ({})", task.description, host);
+    }
+
+    fn task_end(&mut self, _task: &crate::ferro::Task, result: &crate::ferro::TaskResult) {
+        let line = match result.status {
+            Status::Failed => {
+                let error = result.error.as_ref().map_or("", |e| e.as_str());
+                self.paint(
+                    RED,
+                    &format!("failed: [{}] => {}", host(result), error.trim()),
+                )
+            }
+            Status::Skipped => {
+                let reason = result.skip_reason.as_ref().map_or("", |r| r.as_str());
+                format!("skipping: [{}] => {}", host(result), reason)
+            }
+            Status::Changed => self.paint(YELLOW, &format!("changed: [{}]", host(result))),
+            Status::Ok => self.paint(GREEN, &format!("ok: [{}]", host(result))),
+        };
+        let _ = writeln!(self.out, "{}", line);
+    }
+
+    fn playbook_end(&mut self, result: &crate::ferro::PlaybookResult) {
+        let mut recaps: BTreeMap<&str, Recap> = BTreeMap::new();
+        for result in &result.results {
+            let recap = recaps.entry(host(result)).or_default();
+            match result.status {
+                Status::Failed if result.rescued => recap.rescued += 1,
+                Status::Failed => recap.failed += 1,
+                Status::Skipped => recap.skipped += 1,
+                Status::Changed => recap.changed += 1,
+                Status::Ok => recap.ok += 1,
+            }
+        }
+
+        if let Some(error) = &result.error {
+            let _ = writeln!(
+                self.out,
+                "{}",
+                self.paint(RED, &format!("ERROR: {}", error))
+            );
+        }
+        let _ = writeln!(self.out, "\nPLAY RECAP");
+        for (host, recap) in recaps {
+            let line = format!(
+                "{:<24} ok={:<4} changed={:<4} failed={:<4} skipped={:<4} rescued={:<4}",
+                host, recap.ok, recap.changed, recap.failed, recap.skipped, recap.rescued
+            );
+            let color = if recap.failed > 0 {
+                RED
+            } else if recap.changed > 0 {
+                YELLOW
+            } else {
+                GREEN
+            };
+            let _ = writeln!(self.out, "{}", self.paint(color, &line));
+        }
+
+        let mut slowest: Vec<&crate::ferro::TaskResult> =
+            result.results.iter().map(|r| r.as_ref()).collect();
+        slowest.sort_by(|a, b| b.duration.partial_cmp(&a.duration).unwrap());
+        let _ = writeln!(self.out, "\nSLOWEST TASKS");
+        for task in slowest.iter().take(SLOWEST) {
+            let _ = writeln!(
+                self.out,
+                "{:<48} {:>9.2}s ({})",
+                task.task,
+                task.duration,
+                host(task)
+            );
+        }
+        if !result.warnings.is_empty() {
+            let _ = writeln!(self.out, "\nWARNINGS");
+            for warning in &result.warnings {
+                let _ = writeln!(self.out, "{}", self.paint(YELLOW, warning));
+            }
+        }
+        let _ = writeln!(self.out, "\nTotal time: {:.2}s", result.duration);
+        let _ = self.out.flush();
+    }
+
+    fn streams_output(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,67 @@
+use std::io;
+
+use serde::Serialize;
+
+use super::Reporter;
+
+pub const JSON: &str = "json";
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    TaskEnd {
+        result: &'a crate::ferro::TaskResult,
+    },
+    PlaybookEnd {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<&'a str>,
+        ok: usize,
+        changed: usize,
+        failed: usize,
+        skipped: usize,
+        rescued: usize,
+        #[serde(skip_serializing_if = "<[String]>::is_empty")]
+        warnings: &'a [String],
+        start: chrono::DateTime<chrono::Utc>,
+        end: chrono::DateTime<chrono::Utc>,
+        duration: f64,
+    },
+}
+
+pub struct JsonLines {
+    out: Box<dyn io::Write + Send>,
+}
+
+impl JsonLines {
+    pub fn new(out: Box<dyn io::Write + Send>) -> Self {
+        JsonLines { out: out }
+    }
+
+    fn write(&mut self, event: &Event) {
+        if let Ok(line) = serde_json::to_string(event) {
+            let _ = writeln!(self.out, "{}", line);
+        }
+    }
+}
+
+impl Reporter for JsonLines {
+    fn task_end(&mut self, _task: &crate::ferro::Task, result: &crate::ferro::TaskResult) {
+        self.write(&Event::TaskEnd { result: result });
+    }
+
+    fn playbook_end(&mut self, result: &crate::ferro::PlaybookResult) {
+        self.write(&Event::PlaybookEnd {
+            error: result.error.as_deref(),
+            ok: result.ok,
+            changed: result.changed,
+            failed: result.failed,
+            skipped: result.skipped,
+            rescued: result.rescued,
+            warnings: &result.warnings,
+            start: result.start,
+            end: result.end,
+            duration: result.duration,
+        });
+        let _ = self.out.flush();
+    }
+}
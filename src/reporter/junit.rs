@@ -0,0 +1,198 @@
+use std::io;
+
+use super::{host, Reporter};
+
+pub const JUNIT: &str = "junit";
+
+pub struct JUnit {
+    out: Box<dyn io::Write + Send>,
+}
+
+impl JUnit {
+    pub fn new(out: Box<dyn io::Write + Send>) -> Self {
+        JUnit { out: out }
+    }
+}
+
+impl Reporter for JUnit {
+    fn playbook_end(&mut self, result: &crate::ferro::PlaybookResult) {
+        // An error that stopped the playbook, rather than a task, is reported
+        // as an extra testcase so that CI does not see an empty passing suite.
+        let errors = if result.error.is_some() { 1 } else { 0 };
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuites>\n  <testsuite name=\"ferro\" tests=\"{}\" failures=\"{}\" \
+             errors=\"{}\" skipped=\"{}\" time=\"{:.3}\" timestamp=\"{}\">\n",
+            result.results.len() + errors,
+            result.failed,
+            errors,
+            result.skipped,
+            result.duration,
+            result.start.to_rfc3339()
+        ));
+        if let Some(error) = &result.error {
+            xml.push_str(&format!(
+                "    <testcase classname=\"ferro\" name=\"playbook\" time=\"{:.3}\">\n      \
+                 <error message=\"{}\">{}</error>\n    </testcase>\n",
+                result.duration,
+                escape(error.lines().next().unwrap_or("")),
+                escape(error)
+            ));
+        }
+        for result in &result.results {
+            xml.push_str(&format!(
+                "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                escape(host(result)),
+                escape(&result.task),
+                result.duration
+            ));
+            match &result.error {
+                Some(error) if !result.succeeded && !result.rescued => {
+                    xml.push_str(">\n");
+                    xml.push_str(&format!(
+                        "      <failure message=\"{}\">{}</failure>\n",
+                        escape(error.lines().next().unwrap_or("")),
+                        escape(error)
+                    ));
+                    xml.push_str("    </testcase>\n");
+                }
+                _ if result.status == crate::ferro::Status::Skipped => {
+                    let reason = result.skip_reason.as_ref().map_or("", |r| r.as_str());
+                    xml.push_str(&format!(
+                        ">\n      <skipped message=\"{}\"/>\n    </testcase>\n",
+                        escape(reason)
+                    ));
+                }
+                _ => xml.push_str("/>\n"),
+            }
+        }
+        xml.push_str("  </testsuite>\n</testsuites>\n");
+        let _ = self.out.write_all(xml.as_bytes());
+        let _ = self.out.flush();
+    }
+}
+
+// Escapes text for an attribute or element, dropping ANSI escape sequences and
+// the control characters that XML 1.0 does not allow.
+pub fn escape(s: &str) -> String {
+    let mut chars = s.chars().peekable();
+    let mut escaped = String::new();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // A CSI sequence ends at the first byte in the range @ to ~.
+            if chars.peek() == Some(&'[') {
+                chars.next();
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            continue;
+        }
+        escaped.push_str(&match c {
+            '&' => "&amp;".to_owned(),
+            '<' => "&lt;".to_owned(),
+            '>' => "&gt;".to_owned(),
+            '"' => "&quot;".to_owned(),
+            '\'' => "&apos;".to_owned(),
+            '\t' | '\n' | '\r' => c.to_string(),
+            c if c < ' ' || c == '\u{fffe}' || c == '\u{ffff}' => continue,
+            c => c.to_string(),
+        });
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_junit() {
+        let buffer = Buffer::default();
+        let mut reporter = JUnit::new(Box::new(buffer.clone()));
+        let results = vec![
+            Box::new(crate::ferro::TaskResult {
+                task: "create <stack>".to_owned(),
+                module: "cloudformation".to_owned(),
+                host: None,
+                status: crate::ferro::Status::Changed,
+                succeeded: true,
+                changed: true,
+                skip_reason: None,
+                error: None,
+                output: None,
+                start: chrono::Utc::now(),
+                end: chrono::Utc::now(),
+                duration: 0.0,
+                rescued: false,
+            }),
+            Box::new(crate::ferro::TaskResult {
+                task: "run command".to_owned(),
+                module: "command".to_owned(),
+                host: Some("web1".to_owned()),
+                status: crate::ferro::Status::Failed,
+                succeeded: false,
+                changed: true,
+                skip_reason: None,
+                error: Some("exit \"1\"".to_owned()),
+                output: None,
+                start: chrono::Utc::now(),
+                end: chrono::Utc::now(),
+                duration: 1.5,
+                rescued: false,
+            }),
+        ];
+        let result = crate::ferro::PlaybookResult::new(
+            results,
+            chrono::Utc::now(),
+            std::time::Instant::now(),
+        );
+        reporter.playbook_end(&result);
+
+        let xml = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(xml.contains("tests=\"2\" failures=\"1\" errors=\"0\" skipped=\"0\""));
+        assert!(xml.contains(
+            "<testcase classname=\"localhost\" name=\"create &lt;stack&gt;\" time=\"0.000\"/>"
+        ));
+        assert!(xml.contains("name=\"run command\" time=\"1.500\">"));
+        assert!(xml.contains("<failure message=\"exit &quot;1&quot;\">"));
+    }
+
+    #[test]
+    fn test_junit_playbook_error() {
+        let buffer = Buffer::default();
+        let mut reporter = JUnit::new(Box::new(buffer.clone()));
+        let result = crate::ferro::PlaybookResult::error(
+            "unable to lock state".to_owned(),
+            chrono::Utc::now(),
+            std::time::Instant::now(),
+        );
+        reporter.playbook_end(&result);
+
+        let xml = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(xml.contains("tests=\"1\" failures=\"0\" errors=\"1\""));
+        assert!(xml.contains("<error message=\"unable to lock state\">"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("\x1b[31mfailed\x1b[0m <x>"), "failed &lt;x&gt;");
+        assert_eq!(escape("a\u{0}b\u{8}c\td\ne"), "abc\td\ne");
+    }
+}
@@ -0,0 +1,46 @@
+use std::fs;
+use std::io;
+
+pub mod human;
+pub mod json;
+pub mod junit;
+
+pub const LOCALHOST: &str = "localhost";
+
+pub trait Reporter: Send {
+    fn task_start(&mut self, _task: &crate::ferro::Task, _context: &crate::ferro::Context) {}
+    fn task_end(&mut self, _task: &crate::ferro::Task, _result: &crate::ferro::TaskResult) {}
+    fn playbook_end(&mut self, _result: &crate::ferro::PlaybookResult) {}
+    // Whether command output should be streamed to stderr while tasks run.
+    fn streams_output(&self) -> bool {
+        false
+    }
+}
+
+pub struct Null;
+
+impl Reporter for Null {}
+
+pub fn writer(path: Option<&str>) -> Result<Box<dyn io::Write + Send>, io::Error> {
+    match path {
+        Some(path) => Ok(Box::new(fs::File::create(path)?)),
+        None => Ok(Box::new(io::stdout())),
+    }
+}
+
+pub fn from_name(
+    name: &str,
+    out: Box<dyn io::Write + Send>,
+    colored: bool,
+) -> Result<Box<dyn Reporter>, String> {
+    match name {
+        human::HUMAN => Ok(Box::new(human::Human::new(out, colored))),
+        json::JSON => Ok(Box::new(json::JsonLines::new(out))),
+        junit::JUNIT => Ok(Box::new(junit::JUnit::new(out))),
+        _ => Err(format!("unknown reporter {}", name)),
+    }
+}
+
+pub fn host(result: &crate::ferro::TaskResult) -> &str {
+    result.host.as_ref().map_or(LOCALHOST, |h| h.as_str())
+}
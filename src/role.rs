@@ -0,0 +1,167 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use serde_yaml::Value;
+
+use crate::include::{Error, Loader};
+use crate::inventory::file::yaml_vars;
+
+pub const DEFAULT_ROLES_PATH: &str = "roles";
+pub const ROLE_PATH: &str = "role_path";
+
+const MAIN: &str = "main.yml";
+
+pub struct Role {
+    pub name: String,
+    pub path: PathBuf,
+    pub params: HashMap<String, String>,
+}
+
+impl Role {
+    pub fn find(name: &str, roles_path: &[PathBuf]) -> Result<Role, Error> {
+        roles_path
+            .iter()
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_dir())
+            .map(|path| Role {
+                name: name.to_owned(),
+                path: path,
+                params: HashMap::new(),
+            })
+            .ok_or_else(|| {
+                Error::NotFoundError(format!(
+                    "role {} in {}",
+                    name,
+                    roles_path
+                        .iter()
+                        .map(|p| p.display().to_string())
+                        .collect::<Vec<String>>()
+                        .join(":")
+                ))
+            })
+    }
+
+    pub fn load(&self) -> Result<(crate::ferro::Task, Vec<crate::ferro::Task>), Error> {
+        self.load_within(&Rc::default())
+    }
+
+    // Loads the role while the task files in loading are being loaded, so
+    // that a role which ends up including itself is reported as a cycle.
+    pub fn load_within(
+        &self,
+        loading: &Rc<RefCell<Vec<PathBuf>>>,
+    ) -> Result<(crate::ferro::Task, Vec<crate::ferro::Task>), Error> {
+        let loader = Loader {
+            files: Some(self.path.join("files")),
+            templates: Some(self.path.join("templates")),
+            loading: loading.clone(),
+            ..Loader::new(&self.path.join("tasks"))
+        };
+
+        let mut vars = self.vars("vars")?;
+        vars.extend(self.params.clone());
+        vars.insert(ROLE_PATH.to_owned(), self.path.display().to_string());
+        let tasks = self.tasks(&loader, "tasks")?;
+        let handler_loader = Loader {
+            loading: loading.clone(),
+            ..Loader::new(&self.path.join("handlers"))
+        };
+        let mut handlers = self.tasks(&handler_loader, "handlers")?;
+        handlers.extend(loader.handlers.into_inner());
+
+        let task = crate::ferro::Task {
+            description: format!("role {}", self.name),
+            block: Some(crate::ferro::Block {
+                tasks: tasks,
+                vars: vars,
+                defaults: self.vars("defaults")?,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        Ok((task, handlers))
+    }
+
+    fn tasks(&self, loader: &Loader, dir: &str) -> Result<Vec<crate::ferro::Task>, Error> {
+        if self.path.join(dir).join(MAIN).is_file() {
+            loader.tasks(Path::new(MAIN))
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    fn vars(&self, dir: &str) -> Result<HashMap<String, String>, Error> {
+        let path = self.path.join(dir).join(MAIN);
+        if !path.is_file() {
+            return Ok(HashMap::new());
+        }
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| Error::IoError(format!("{}: {}", path.display(), e)))?;
+        match serde_yaml::from_str(&contents) {
+            Ok(Value::Mapping(vars)) => Ok(yaml_vars(&vars)),
+            Ok(Value::Null) => Ok(HashMap::new()),
+            Ok(_) => Err(Error::ParseError(format!(
+                "{} must be a mapping",
+                path.display()
+            ))),
+            Err(e) => Err(Error::ParseError(format!("{}: {}", path.display(), e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let role = root.join("roles").join("web");
+        for dir in &["defaults", "vars", "tasks", "handlers", "templates"] {
+            fs::create_dir_all(role.join(dir)).unwrap();
+        }
+        let files = vec![
+            ("defaults/main.yml", "port: 80\ngreeting: hi\n"),
+            ("vars/main.yml", "greeting: hello\n"),
+            (
+                "tasks/main.yml",
+                "- name: render\n  template:\n    src: motd.j2\n    dest: \"{{ dest }}\"\n  \
+                 notify: restart\n- include_tasks: extra.yml\n",
+            ),
+            (
+                "tasks/extra.yml",
+                "- name: greet\n  command: /bin/echo {{ greeting }} {{ port }}\n",
+            ),
+            (
+                "handlers/main.yml",
+                "- name: restart\n  shell: echo restarted\n",
+            ),
+            ("templates/motd.j2", "port {{ port }}\n"),
+        ];
+        for (path, contents) in files {
+            fs::write(role.join(path), contents).unwrap();
+        }
+
+        let dest = root.join("motd");
+        let mut playbook = crate::ferro::Playbook {
+            roles_path: vec![root.join("roles")],
+            ..Default::default()
+        };
+        let mut params = HashMap::new();
+        params.insert("port".to_owned(), "8080".to_owned());
+        params.insert("dest".to_owned(), dest.display().to_string());
+        playbook.role("web", params).unwrap();
+        assert!(playbook.role("missing", HashMap::new()).is_err());
+
+        let result = playbook.run();
+        assert!(result.succeeded());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "port 8080\n");
+        let value = &playbook.context.state["greet"];
+        assert_eq!(crate::ferro::find("stdout", value).unwrap(), "hello 8080\n");
+        assert!(result.results.iter().any(|r| r.task == "restart"));
+    }
+}
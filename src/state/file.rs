@@ -0,0 +1,105 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+
+use super::{lock_id, Error, State};
+
+const LOCK_EXTENSION: &str = "lock";
+
+pub struct File {
+    pub path: PathBuf,
+}
+
+impl File {
+    fn lock_path(&self) -> PathBuf {
+        let mut lock_path = self.path.clone().into_os_string();
+        lock_path.push(".");
+        lock_path.push(LOCK_EXTENSION);
+        PathBuf::from(lock_path)
+    }
+}
+
+impl super::Backend for File {
+    fn lock(&self) -> Result<(), Error> {
+        let lock_path = self.lock_path();
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(mut lock) => lock
+                .write_all(lock_id().as_bytes())
+                .map_err(|e| Error::IoError(e.to_string())),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let holder = fs::read_to_string(&lock_path).unwrap_or_default();
+                Err(Error::LockedError(format!(
+                    "{} is held by {}",
+                    lock_path.display(),
+                    holder
+                )))
+            }
+            Err(e) => Err(Error::IoError(e.to_string())),
+        }
+    }
+
+    fn unlock(&self) -> Result<(), Error> {
+        fs::remove_file(self.lock_path()).map_err(|e| Error::IoError(e.to_string()))
+    }
+
+    fn load(&self) -> Result<State, Error> {
+        match fs::read(&self.path) {
+            Ok(contents) => {
+                serde_json::from_slice(&contents).map_err(|e| Error::ParseError(e.to_string()))
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(State::default()),
+            Err(e) => Err(Error::IoError(e.to_string())),
+        }
+    }
+
+    fn save(&self, state: &State) -> Result<(), Error> {
+        let contents =
+            serde_json::to_vec_pretty(state).map_err(|e| Error::ParseError(e.to_string()))?;
+        let mut staging = self.path.clone().into_os_string();
+        staging.push(".tmp");
+        fs::write(&staging, contents)
+            .and_then(|_| fs::rename(&staging, &self.path))
+            .map_err(|e| Error::IoError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Backend;
+    use serde_json::json;
+
+    #[test]
+    fn test_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let backend = File { path: path.clone() };
+
+        backend.lock().unwrap();
+        assert!(backend.lock().is_err());
+
+        let mut state = backend.load().unwrap();
+        assert_eq!(state.serial, 0);
+        state.serial += 1;
+        state.update(
+            &None,
+            vec![("stack".to_owned(), json!({"outputs": {"Vpc": "vpc-1"}}))]
+                .into_iter()
+                .collect(),
+        );
+        backend.save(&state).unwrap();
+        backend.unlock().unwrap();
+
+        let state = backend.load().unwrap();
+        assert_eq!(state.serial, 1);
+        assert_eq!(
+            state.host(&None)["stack"],
+            json!({"outputs": {"Vpc": "vpc-1"}})
+        );
+    }
+}
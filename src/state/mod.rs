@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::Value;
+
+pub mod file;
+pub mod s3;
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(String),
+    ParseError(String),
+    LockedError(String),
+    BackendError(String),
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IoError(e) => write!(f, "unable to access state: {}", e),
+            Error::ParseError(e) => write!(f, "unable to parse state: {}", e),
+            Error::LockedError(e) => write!(f, "state is locked: {}", e),
+            Error::BackendError(e) => write!(f, "state backend failed: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    pub serial: u64,
+    pub hosts: HashMap<String, HashMap<String, Value>>,
+}
+
+impl State {
+    pub fn host(&self, host: &Option<String>) -> HashMap<String, Value> {
+        self.hosts.get(host_key(host)).cloned().unwrap_or_default()
+    }
+
+    pub fn update(&mut self, host: &Option<String>, state: HashMap<String, Value>) {
+        self.hosts
+            .entry(host_key(host).to_owned())
+            .or_default()
+            .extend(state);
+    }
+}
+
+pub trait Backend: Send {
+    fn lock(&self) -> Result<(), Error>;
+    fn unlock(&self) -> Result<(), Error>;
+    fn load(&self) -> Result<State, Error>;
+    fn save(&self, state: &State) -> Result<(), Error>;
+}
+
+pub fn host_key(host: &Option<String>) -> &str {
+    host.as_ref()
+        .map_or(crate::reporter::LOCALHOST, |h| h.as_str())
+}
+
+pub fn lock_id() -> String {
+    let hostname = std::env::var("HOSTNAME").unwrap_or_else(|_| "unknown".to_owned());
+    format!("{}:{}", hostname, std::process::id())
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::default::Default;
+use std::io::Read;
+
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    AttributeValue, DeleteItemInput, DynamoDb, DynamoDbClient, GetItemInput, PutItemError,
+    PutItemInput,
+};
+use rusoto_s3::{GetObjectError, GetObjectRequest, PutObjectRequest, S3Client, S3 as S3Api};
+
+use super::{lock_id, Error, State};
+
+const LOCK_ID: &str = "LockID";
+const OWNER: &str = "Owner";
+
+pub struct S3 {
+    pub bucket: String,
+    pub key: String,
+    pub lock_table: Option<String>,
+    pub s3: S3Client,
+    pub dynamodb: DynamoDbClient,
+}
+
+impl S3 {
+    // Without an endpoint the region comes from the environment, including
+    // AWS_ENDPOINT_URL; see lookup::aws::region.
+    pub fn new(
+        bucket: String,
+        key: String,
+        lock_table: Option<String>,
+        endpoint: Option<&str>,
+    ) -> Self {
+        let region = match endpoint {
+            Some(endpoint) => crate::lookup::aws::endpoint_region(endpoint),
+            None => crate::lookup::aws::region(),
+        };
+        S3 {
+            bucket: bucket,
+            key: key,
+            lock_table: lock_table,
+            s3: S3Client::new(region.clone()),
+            dynamodb: DynamoDbClient::new(region),
+        }
+    }
+
+    fn lock_key(&self) -> HashMap<String, AttributeValue> {
+        let mut key = HashMap::new();
+        key.insert(
+            LOCK_ID.to_owned(),
+            AttributeValue {
+                s: Some(format!("{}/{}", self.bucket, self.key)),
+                ..Default::default()
+            },
+        );
+        key
+    }
+
+    fn lock_holder(&self, table: &str) -> String {
+        self.dynamodb
+            .get_item(GetItemInput {
+                table_name: table.to_owned(),
+                key: self.lock_key(),
+                consistent_read: Some(true),
+                ..Default::default()
+            })
+            .sync()
+            .ok()
+            .and_then(|output| output.item)
+            .and_then(|item| item.get(OWNER).and_then(|owner| owner.s.clone()))
+            .unwrap_or_else(|| "unknown".to_owned())
+    }
+}
+
+impl super::Backend for S3 {
+    fn lock(&self) -> Result<(), Error> {
+        let table = match &self.lock_table {
+            Some(table) => table,
+            None => return Ok(()),
+        };
+        let mut item = self.lock_key();
+        item.insert(
+            OWNER.to_owned(),
+            AttributeValue {
+                s: Some(lock_id()),
+                ..Default::default()
+            },
+        );
+        let result = self
+            .dynamodb
+            .put_item(PutItemInput {
+                table_name: table.to_owned(),
+                item: item,
+                condition_expression: Some(format!("attribute_not_exists({})", LOCK_ID)),
+                ..Default::default()
+            })
+            .sync();
+        match result {
+            Ok(_) => Ok(()),
+            Err(RusotoError::Service(PutItemError::ConditionalCheckFailed(_))) => Err(
+                Error::LockedError(format!("{} is held by {}", table, self.lock_holder(table))),
+            ),
+            Err(e) => Err(Error::BackendError(e.to_string())),
+        }
+    }
+
+    fn unlock(&self) -> Result<(), Error> {
+        let table = match &self.lock_table {
+            Some(table) => table,
+            None => return Ok(()),
+        };
+        self.dynamodb
+            .delete_item(DeleteItemInput {
+                table_name: table.to_owned(),
+                key: self.lock_key(),
+                ..Default::default()
+            })
+            .sync()
+            .map(|_| ())
+            .map_err(|e| Error::BackendError(e.to_string()))
+    }
+
+    fn load(&self) -> Result<State, Error> {
+        let result = self
+            .s3
+            .get_object(GetObjectRequest {
+                bucket: self.bucket.clone(),
+                key: self.key.clone(),
+                ..Default::default()
+            })
+            .sync();
+        let output = match result {
+            Ok(output) => output,
+            Err(RusotoError::Service(GetObjectError::NoSuchKey(_))) => return Ok(State::default()),
+            Err(e) => return Err(Error::BackendError(e.to_string())),
+        };
+
+        let mut contents = vec![];
+        if let Some(body) = output.body {
+            body.into_blocking_read()
+                .read_to_end(&mut contents)
+                .map_err(|e| Error::IoError(e.to_string()))?;
+        }
+        serde_json::from_slice(&contents).map_err(|e| Error::ParseError(e.to_string()))
+    }
+
+    fn save(&self, state: &State) -> Result<(), Error> {
+        let contents =
+            serde_json::to_vec_pretty(state).map_err(|e| Error::ParseError(e.to_string()))?;
+        self.s3
+            .put_object(PutObjectRequest {
+                bucket: self.bucket.clone(),
+                key: self.key.clone(),
+                body: Some(contents.into()),
+                content_type: Some("application/json".to_owned()),
+                ..Default::default()
+            })
+            .sync()
+            .map(|_| ())
+            .map_err(|e| Error::BackendError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Backend;
+
+    // Requires S3 and DynamoDB at a local endpoint (e.g. localstack on port
+    // 4566) with a ferro-test bucket and a ferro-locks table keyed by LockID.
+    #[test]
+    #[ignore]
+    fn test_s3_local_endpoint() {
+        let backend = S3::new(
+            "ferro-test".to_owned(),
+            format!("state-{}.json", std::process::id()),
+            Some("ferro-locks".to_owned()),
+            Some("http://localhost:4566"),
+        );
+        assert_eq!(backend.load().unwrap().serial, 0);
+
+        let mut state = State::default();
+        state.serial = 3;
+        backend.save(&state).unwrap();
+        assert_eq!(backend.load().unwrap().serial, 3);
+
+        backend.lock().unwrap();
+        match backend.lock() {
+            Err(Error::LockedError(holder)) => assert!(holder.contains(&lock_id())),
+            other => panic!("expected a lock error, got {:?}", other.err()),
+        }
+        backend.unlock().unwrap();
+        backend.lock().unwrap();
+        backend.unlock().unwrap();
+    }
+}
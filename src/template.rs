@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+const LOOKUP: &str = "lookup(";
+
+#[derive(Debug)]
+pub enum Error {
+    UndefinedError(String),
+    SyntaxError(String),
+    LookupError(String),
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UndefinedError(e) => write!(f, "undefined variable {}", e),
+            Error::SyntaxError(e) => write!(f, "invalid template: {}", e),
+            Error::LookupError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+pub fn render(template: &str, vars: &HashMap<String, String>) -> Result<String, Error> {
+    render_with(template, vars, None)
+}
+
+pub fn render_context(template: &str, context: &crate::ferro::Context) -> Result<String, Error> {
+    if context.secrets.is_empty() {
+        return render_with(template, &context.vars, Some(&context.lookups));
+    }
+    let mut vars = context.vars.clone();
+    vars.extend(
+        context
+            .secrets
+            .iter()
+            .map(|(k, v)| (k.to_owned(), v.expose().to_owned())),
+    );
+    render_with(template, &vars, Some(&context.lookups))
+}
+
+fn render_with(
+    template: &str,
+    vars: &HashMap<String, String>,
+    lookups: Option<&crate::lookup::Lookups>,
+) -> Result<String, Error> {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(OPEN) {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + OPEN.len()..];
+        let end = after
+            .find(CLOSE)
+            .ok_or_else(|| Error::SyntaxError(format!("unclosed {} in {}", OPEN, template)))?;
+        let name = after[..end].trim();
+        match (name.starts_with(LOOKUP), lookups) {
+            (true, Some(lookups)) => {
+                let (source, key) = lookup_args(name)?;
+                let value = lookups
+                    .get(&source, &key)
+                    .map_err(|e| Error::LookupError(e.to_string()))?;
+                rendered.push_str(&value);
+            }
+            _ => match vars.get(name) {
+                Some(value) => rendered.push_str(value),
+                None => return Err(Error::UndefinedError(name.to_owned())),
+            },
+        }
+        rest = &after[end + CLOSE.len()..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+fn lookup_args(expression: &str) -> Result<(String, String), Error> {
+    let invalid = || {
+        Error::SyntaxError(format!(
+            "expected lookup('source', 'key') in {}",
+            expression
+        ))
+    };
+    let args = expression[LOOKUP.len()..]
+        .strip_suffix(')')
+        .ok_or_else(invalid)?;
+    let mut args = args.splitn(2, ',').map(|a| {
+        let a = a.trim();
+        if a.len() >= 2
+            && (a.starts_with('\'') && a.ends_with('\'') || a.starts_with('"') && a.ends_with('"'))
+        {
+            Some(a[1..a.len() - 1].to_owned())
+        } else {
+            None
+        }
+    });
+    match (args.next().flatten(), args.next().flatten()) {
+        (Some(source), Some(key)) => Ok((source, key)),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut vars = HashMap::new();
+        vars.insert("name".to_owned(), "web".to_owned());
+        vars.insert("port".to_owned(), "8080".to_owned());
+
+        assert_eq!(
+            render("{{ name }}:{{port}} ok", &vars).unwrap(),
+            "web:8080 ok"
+        );
+        assert_eq!(render("plain", &vars).unwrap(), "plain");
+        assert!(render("{{ missing }}", &vars).is_err());
+        assert!(render("{{ name", &vars).is_err());
+    }
+
+    #[test]
+    fn test_render_lookup() {
+        std::env::set_var("FERRO_TEMPLATE_TEST", "looked up");
+        let context = crate::ferro::Context::default();
+        assert_eq!(
+            render_context("{{ lookup('env', 'FERRO_TEMPLATE_TEST') }}!", &context).unwrap(),
+            "looked up!"
+        );
+        assert_eq!(
+            render_context("{{ lookup(\"pipe\", \"echo a, b\") }}", &context).unwrap(),
+            "a, b"
+        );
+        assert!(render_context("{{ lookup('env') }}", &context).is_err());
+        assert!(render_context("{{ lookup('bogus', 'key') }}", &context).is_err());
+        assert!(render("{{ lookup('env', 'HOME') }}", &HashMap::new()).is_err());
+    }
+}
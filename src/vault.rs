@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use serde::{Serialize, Serializer};
+use serde_yaml::Value;
+
+pub const HEADER: &str = "$FERRO_VAULT;1.0;AES256-GCM";
+pub const REDACTED: &str = "********";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+const ITERATIONS: u32 = 100_000;
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(String),
+    FormatError(String),
+    DecryptError,
+    EncryptError(String),
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IoError(e) => write!(f, "unable to read vault: {}", e),
+            Error::FormatError(e) => write!(f, "invalid vault: {}", e),
+            Error::DecryptError => write!(f, "unable to decrypt vault: wrong password or key"),
+            Error::EncryptError(e) => write!(f, "unable to encrypt vault: {}", e),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Secret(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", REDACTED)
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", REDACTED)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+enum Material {
+    Password(Secret),
+    Key(Vec<u8>),
+}
+
+pub struct Vault {
+    material: Material,
+}
+
+impl Vault {
+    pub fn from_password(password: Secret) -> Self {
+        Vault {
+            material: Material::Password(password),
+        }
+    }
+
+    pub fn from_password_file(path: &Path) -> Result<Self, Error> {
+        let password = fs::read_to_string(path)
+            .map_err(|e| Error::IoError(format!("{}: {}", path.display(), e)))?;
+        Ok(Vault::from_password(Secret::new(
+            password.trim_end_matches(&['\r', '\n'][..]).to_owned(),
+        )))
+    }
+
+    pub fn from_key_file(path: &Path) -> Result<Self, Error> {
+        let contents =
+            fs::read(path).map_err(|e| Error::IoError(format!("{}: {}", path.display(), e)))?;
+        let key = if contents.len() == KEY_LEN {
+            contents
+        } else {
+            base64::decode(String::from_utf8_lossy(&contents).trim()).map_err(|_| {
+                Error::FormatError(format!(
+                    "{} must contain {} raw or base64 encoded bytes",
+                    path.display(),
+                    KEY_LEN
+                ))
+            })?
+        };
+        if key.len() != KEY_LEN {
+            return Err(Error::FormatError(format!(
+                "{} must contain a {} byte key",
+                path.display(),
+                KEY_LEN
+            )));
+        }
+        Ok(Vault {
+            material: Material::Key(key),
+        })
+    }
+
+    fn cipher(&self, salt: &[u8]) -> Aes256Gcm {
+        let mut key = [0u8; KEY_LEN];
+        match &self.material {
+            Material::Password(password) => pbkdf2::pbkdf2_hmac::<sha2::Sha256>(
+                password.expose().as_bytes(),
+                salt,
+                ITERATIONS,
+                &mut key,
+            ),
+            Material::Key(bytes) => key.copy_from_slice(bytes),
+        }
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<String, Error> {
+        let mut random = [0u8; SALT_LEN + NONCE_LEN];
+        getrandom::getrandom(&mut random).map_err(|e| Error::EncryptError(e.to_string()))?;
+        let (salt, nonce) = random.split_at(SALT_LEN);
+        let ciphertext = self
+            .cipher(salt)
+            .encrypt(Nonce::from_slice(nonce), plaintext)
+            .map_err(|e| Error::EncryptError(e.to_string()))?;
+
+        let mut payload = random.to_vec();
+        payload.extend(ciphertext);
+        Ok(format!("{}\n{}\n", HEADER, base64::encode(&payload)))
+    }
+
+    pub fn decrypt(&self, contents: &str) -> Result<Vec<u8>, Error> {
+        let mut lines = contents.lines();
+        if lines.next().map(|l| l.trim()) != Some(HEADER) {
+            return Err(Error::FormatError(format!("missing {} header", HEADER)));
+        }
+        let encoded: String = lines.map(|l| l.trim()).collect();
+        let payload = base64::decode(&encoded).map_err(|e| Error::FormatError(e.to_string()))?;
+        if payload.len() < SALT_LEN + NONCE_LEN {
+            return Err(Error::FormatError("payload is too short".to_owned()));
+        }
+        let (salt, rest) = payload.split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        self.cipher(salt)
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| Error::DecryptError)
+    }
+
+    pub fn load_vars(&self, path: &Path) -> Result<HashMap<String, Secret>, Error> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| Error::IoError(format!("{}: {}", path.display(), e)))?;
+        let plaintext = self.decrypt(&contents)?;
+        match serde_yaml::from_slice(&plaintext) {
+            Ok(Value::Mapping(vars)) => Ok(crate::inventory::file::yaml_vars(&vars)
+                .into_iter()
+                .map(|(k, v)| (k, Secret::new(v)))
+                .collect()),
+            Ok(Value::Null) => Ok(HashMap::new()),
+            _ => Err(Error::FormatError(format!(
+                "{} must contain a mapping of variables",
+                path.display()
+            ))),
+        }
+    }
+}
+
+pub fn is_encrypted(contents: &str) -> bool {
+    contents.trim_start().starts_with(HEADER)
+}
+
+pub fn redact(text: &str, secrets: &[String]) -> String {
+    secrets
+        .iter()
+        .filter(|s| !s.is_empty())
+        .fold(text.to_owned(), |text, secret| {
+            text.replace(secret.as_str(), REDACTED)
+        })
+}
+
+pub fn redact_value(value: &mut serde_json::value::Value, secrets: &[String]) {
+    match value {
+        serde_json::value::Value::String(s) => *s = redact(s, secrets),
+        serde_json::value::Value::Array(values) => {
+            values.iter_mut().for_each(|v| redact_value(v, secrets))
+        }
+        serde_json::value::Value::Object(values) => {
+            values.values_mut().for_each(|v| redact_value(v, secrets))
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vault() {
+        let vault = Vault::from_password(Secret::new("hunter2".to_owned()));
+        let encrypted = vault.encrypt(b"db_password: s3cret\n").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("s3cret"));
+        assert_eq!(vault.decrypt(&encrypted).unwrap(), b"db_password: s3cret\n");
+
+        let wrong = Vault::from_password(Secret::new("wrong".to_owned()));
+        assert!(wrong.decrypt(&encrypted).is_err());
+        assert!(vault.decrypt("plain: text").is_err());
+
+        let secret = Secret::new("s3cret".to_owned());
+        assert_eq!(format!("{} {:?}", secret, secret), "******** ********");
+        assert_eq!(serde_json::to_string(&secret).unwrap(), "\"********\"");
+
+        let secrets = vec![secret.expose().to_owned()];
+        assert_eq!(
+            redact("login s3cret failed", &secrets),
+            "login ******** failed"
+        );
+        let mut value = serde_json::json!({"stdout": ["s3cret"], "code": 1});
+        redact_value(&mut value, &secrets);
+        assert_eq!(
+            value,
+            serde_json::json!({"stdout": ["********"], "code": 1})
+        );
+    }
+}
@@ -1,8 +1,12 @@
 use std::process;
 use std::vec::Vec;
 
-pub trait When {
+pub trait When: Send + Sync {
     fn when(&self) -> Result<bool, crate::ferro::Error>;
+
+    fn reason(&self) -> String {
+        "condition was not met".to_owned()
+    }
 }
 
 #[derive(Debug)]
@@ -21,16 +25,27 @@ impl When for Never {
     fn when(&self) -> Result<bool, crate::ferro::Error> {
         Ok(false)
     }
+
+    fn reason(&self) -> String {
+        "condition is never met".to_owned()
+    }
 }
 
+// Runs the command on the machine running the playbook, not on the task's
+// host, so a `when` cannot inspect remote state; such a check belongs in a
+// task of its own.
 #[derive(Debug)]
 pub struct WhenExecute {
     pub command: String,
     pub args: Vec<String>,
+    pub error: Option<String>,
 }
 
 impl When for WhenExecute {
     fn when(&self) -> Result<bool, crate::ferro::Error> {
+        if let Some(e) = &self.error {
+            return Err(crate::ferro::error(false, e.to_owned()));
+        }
         process::Command::new(self.command.clone())
             .args(self.args.clone())
             .stdin(process::Stdio::null())
@@ -46,19 +61,52 @@ impl When for WhenExecute {
                     }
                 })
             })
-            .map_err(|e| crate::ferro::Error {
-                changed: false,
-                description: e.to_string(),
-            })
+            .map_err(|e| crate::ferro::error(false, e.to_string()))
+    }
+
+    fn reason(&self) -> String {
+        let mut words = vec![self.command.clone()];
+        words.extend(self.args.clone());
+        format!("`{}` exited with a non-zero status", words.join(" "))
     }
 }
 
 pub fn when_execute(execute: &str) -> WhenExecute {
-    let mut parts = execute.split_whitespace();
-    let command = parts.next().unwrap_or("").to_owned();
-    let args = parts.map(|s| s.to_owned()).collect();
-    WhenExecute {
-        command: command,
-        args: args,
+    match shell_words::split(execute) {
+        Ok(parts) => {
+            let mut parts = parts.into_iter();
+            let command = parts.next().unwrap_or_else(|| "".to_owned());
+            let args = parts.collect();
+            WhenExecute {
+                command: command,
+                args: args,
+                error: None,
+            }
+        }
+        Err(e) => WhenExecute {
+            command: "".to_owned(),
+            args: vec![],
+            error: Some(format!("unable to parse command `{}`: {}", execute, e)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_when_execute() {
+        let when = when_execute("/bin/sh -c 'test -d /'");
+        assert_eq!(when.command, "/bin/sh");
+        assert_eq!(when.args, vec!["-c", "test -d /"]);
+        assert!(when.when().unwrap());
+
+        let when = when_execute("/bin/sh -c \"exit 1\"");
+        assert!(!when.when().unwrap());
+
+        let when = when_execute("/bin/echo 'unterminated");
+        assert!(when.error.is_some());
+        assert!(when.when().is_err());
     }
 }